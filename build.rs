@@ -1,4 +1,8 @@
 fn main() {
+    // 只有windows需要嵌入管理员权限清单
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
     let mut res = winres::WindowsResource::new();
     res.set_manifest(
         r#"
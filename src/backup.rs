@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use time::{format_description, OffsetDateTime, UtcOffset};
+
+/// 所有备份都放在用户目录下的这个目录中
+pub const BACKUP_DIR_NAME: &str = "llob_install_backups";
+/// 默认保留的最近的备份数，第一个备份(第一次安装前的样子)总是保留
+pub const DEFAULT_KEEP_BACKUPS: usize = 5;
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// 一次安装前的备份，每个被修改的文件/目录在修改前都先放进来
+pub struct Backup {
+    pub name: String,
+    dir: PathBuf,
+    manifest: serde_json::Value,
+}
+
+fn now_local() -> OffsetDateTime {
+    let utc_offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::from_hms(8, 0, 0).unwrap());
+    OffsetDateTime::now_utc().to_offset(utc_offset)
+}
+
+/// 复制目录，exclude 中的名字只在第一层生效
+fn copy_dir_all(from: &Path, to: &Path, exclude: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if exclude.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to.join(entry.file_name()), &[])?;
+        } else {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// 删除目录中除了 exclude 以外的所有内容
+pub fn clear_dir(dir: &Path, exclude: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if exclude.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+impl Backup {
+    /// 在 backup_root 下新建一个以时间命名的备份
+    pub fn create(backup_root: &Path, qq_path: &Path, qq_inner_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let now = now_local();
+        let base_name = now.format(&format_description::parse("[year][month][day]-[hour][minute][second]")?)?;
+        // 同一秒的备份加上序号，序号总是比已有的大，删除旧备份后也不会重复使用
+        let mut max_index = 0;
+        if let Ok(entries) = fs::read_dir(backup_root) {
+            for entry in entries.flatten() {
+                let entry_name = entry.file_name().to_string_lossy().to_string();
+                if entry_name == base_name {
+                    max_index = max_index.max(1);
+                } else if let Some(index) = entry_name.strip_prefix(&format!("{base_name}-")).and_then(|index| index.parse::<u32>().ok()) {
+                    max_index = max_index.max(index);
+                }
+            }
+        }
+        let name = match max_index {
+            0 => base_name,
+            index => format!("{base_name}-{}", index + 1),
+        };
+        let dir = backup_root.join(&name);
+        fs::create_dir_all(&dir)?;
+        let backup = Backup {
+            name,
+            dir,
+            manifest: serde_json::json!({
+                "created": now.format(&format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]")?)?,
+                "qq_path": qq_path,
+                "qq_inner_path": qq_inner_path,
+                "entries": [],
+            }),
+        };
+        backup.save()?;
+        Ok(backup)
+    }
+
+    pub fn open(backup_root: &Path, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let dir = backup_root.join(name);
+        let manifest_str = fs::read_to_string(dir.join(MANIFEST_NAME)).map_err(|_| format!("备份{name}不存在"))?;
+        let manifest: serde_json::Value = serde_json::from_str(&manifest_str)?;
+        Ok(Backup {
+            name: name.to_owned(),
+            dir,
+            manifest,
+        })
+    }
+
+    /// 列出所有备份，从旧到新排列
+    pub fn list(backup_root: &Path) -> Result<Vec<Backup>, Box<dyn std::error::Error>> {
+        let mut names = vec![];
+        if backup_root.is_dir() {
+            for entry in fs::read_dir(backup_root)? {
+                let entry = entry?;
+                if entry.path().join(MANIFEST_NAME).is_file() {
+                    names.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        // 同一秒的备份名后面有 -2、-10 这样的序号，要按数值排序
+        names.sort_by(|a, b| crate::release::compare_versions(a, b));
+        names.iter().map(|name| Backup::open(backup_root, name)).collect()
+    }
+
+    /// 只保留第一个备份和最近的 keep 个备份，返回删除的备份名
+    pub fn prune(backup_root: &Path, keep: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let backups = Backup::list(backup_root)?;
+        let mut removed = vec![];
+        if backups.len() <= keep + 1 {
+            return Ok(removed);
+        }
+        for backup in &backups[1..backups.len() - keep] {
+            backup.remove()?;
+            removed.push(backup.name.clone());
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(self.dir.join(MANIFEST_NAME), serde_json::to_string_pretty(&self.manifest)?)?;
+        Ok(())
+    }
+
+    /// 最早备份了 path 的备份中它的内容，也就是第一次修改前的样子
+    /// 没有备份过 path 或者备份时它不存在时返回None
+    pub fn original_file(backup_root: &Path, path: &Path) -> Option<Vec<u8>> {
+        for backup in Backup::list(backup_root).ok()? {
+            let entries = backup.manifest["entries"].as_array()?;
+            let Some(entry) = entries.iter().find(|entry| entry["path"].as_str() == path.to_str()) else {
+                continue;
+            };
+            return match (entry["kind"].as_str(), entry["backup"].as_str()) {
+                (Some("file"), Some(name)) => fs::read(backup.dir.join(name)).ok(),
+                _ => None,
+            };
+        }
+        None
+    }
+
+    pub fn manifest(&self) -> &serde_json::Value {
+        &self.manifest
+    }
+
+    pub fn qq_path(&self) -> Option<PathBuf> {
+        self.manifest["qq_path"].as_str().map(PathBuf::from)
+    }
+
+    pub fn qq_inner_path(&self) -> Option<PathBuf> {
+        self.manifest["qq_inner_path"].as_str().map(PathBuf::from)
+    }
+
+    /// 在修改 path 之前调用，path 不存在时也会记录下来，还原时会删除它
+    /// path 是目录时，exclude 中的子目录不备份，还原时也不会动它们
+    pub fn snapshot(&mut self, path: &Path, exclude: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = self.manifest["entries"].as_array_mut().ok_or("备份清单损坏")?;
+        // 同一个文件只需要备份第一次修改前的样子
+        if entries.iter().any(|entry| entry["path"].as_str() == path.to_str()) {
+            return Ok(());
+        }
+        let backup_name = format!("{}", entries.len());
+        // 只保留原来就有的，修改时新建的在还原时一并删除
+        let exclude: Vec<&str> = exclude.iter().copied().filter(|name| path.join(name).exists()).collect();
+        let entry;
+        if path.is_dir() {
+            copy_dir_all(path, &self.dir.join(&backup_name), &exclude)?;
+            entry = serde_json::json!({
+                "path": path,
+                "kind": "dir",
+                "backup": backup_name,
+                "exclude": exclude,
+            });
+        } else if path.is_file() {
+            fs::copy(path, self.dir.join(&backup_name))?;
+            entry = serde_json::json!({
+                "path": path,
+                "kind": "file",
+                "backup": backup_name,
+            });
+        } else {
+            entry = serde_json::json!({
+                "path": path,
+                "kind": "absent",
+                "exclude": exclude,
+            });
+        }
+        log::info!("已备份{path:?}");
+        entries.push(entry);
+        self.save()
+    }
+
+    /// 删除这个备份
+    pub fn remove(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+
+    /// 把备份中记录的文件/目录还原到备份时的样子
+    pub fn restore(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = self.manifest["entries"].as_array().ok_or("备份清单损坏")?;
+        for entry in entries.iter().rev() {
+            let path = PathBuf::from(entry["path"].as_str().ok_or("备份清单损坏")?);
+            let backup_path = entry["backup"].as_str().map(|name| self.dir.join(name));
+            let exclude: Vec<&str> = entry["exclude"]
+                .as_array()
+                .map(|names| names.iter().filter_map(|name| name.as_str()).collect())
+                .unwrap_or_default();
+            match (entry["kind"].as_str(), backup_path) {
+                (Some("file"), Some(backup_path)) => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(backup_path, &path)?;
+                }
+                (Some("dir"), Some(backup_path)) => {
+                    if path.is_dir() {
+                        clear_dir(&path, &exclude)?;
+                    }
+                    copy_dir_all(&backup_path, &path, &[])?;
+                }
+                (Some("absent"), _) => {
+                    if path.is_dir() && !exclude.is_empty() {
+                        clear_dir(&path, &exclude)?;
+                    } else if path.is_dir() {
+                        fs::remove_dir_all(&path)?;
+                    } else if path.is_file() {
+                        fs::remove_file(&path)?;
+                    }
+                }
+                _ => return Err("备份清单损坏".into()),
+            }
+            log::info!("已还原{path:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{io::Write, path::Path};
+
+use crate::source::{self, sha256_hex, Checksums, Component, Source};
+
+/// 离线包中需要包含的文件，两种架构的修补文件都要带上
+const BUNDLE_FILES: &[(Component, &str)] = &[
+    (Component::VerifyPatch, "dbghelp_x64.dll"),
+    (Component::VerifyPatch, "dbghelp_x86.dll"),
+    (Component::LiteLoader, source::LITELOADER_FILE_NAME),
+    (Component::LLOneBot, source::LLONEBOT_FILE_NAME),
+];
+
+/// 下载所有组件并打包成离线包，返回离线包的清单
+pub fn build(source: &dyn Source, checksums: &Checksums, output: &Path) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let mut components = serde_json::Map::new();
+    let mut files = vec![];
+    for (component, file_name) in BUNDLE_FILES {
+        let artifact = source::fetch_verified(source, *component, file_name, checksums)?;
+        let sha256 = sha256_hex(&artifact.data);
+        let entry = components.entry(component.name()).or_insert_with(|| {
+            serde_json::json!({
+                "version": artifact.version,
+                "files": [],
+            })
+        });
+        entry["files"].as_array_mut().ok_or("清单格式错误")?.push(serde_json::json!({
+            "name": file_name,
+            "size": artifact.data.len(),
+            "sha256": sha256,
+        }));
+        files.push((file_name, artifact.data));
+    }
+    let manifest = serde_json::json!({
+        "format": 1,
+        "generator": format!("llob_install {}", env!("CARGO_PKG_VERSION")),
+        "components": components,
+    });
+
+    log::info!("正在写入离线包{output:?}...");
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // 先写到临时文件，避免留下不完整的离线包
+    let tmp_path = output.with_extension("tmp");
+    {
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&tmp_path)?);
+        let options = zip::write::FileOptions::default();
+        writer.start_file(source::BUNDLE_MANIFEST_NAME, options)?;
+        writer.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+        for (file_name, data) in files {
+            writer.start_file(*file_name, options)?;
+            writer.write_all(&data)?;
+        }
+        writer.finish()?;
+    }
+    std::fs::rename(&tmp_path, output)?;
+    log::info!("离线包已生成: {output:?}");
+    Ok(manifest)
+}
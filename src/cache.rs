@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::source::{sha256_hex, Component};
+
+/// 每个缓存的文件旁边都有一个这样后缀的文件，记录版本和sha256
+const META_SUFFIX: &str = ".meta.json";
+
+/// 下载缓存，按 组件/版本/文件名 存放
+pub struct Cache {
+    dir: PathBuf,
+}
+
+/// 版本号中可能有不能用作目录名的字符
+fn sanitize(version: &str) -> String {
+    version
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect()
+}
+
+fn meta_path(file_path: &Path) -> PathBuf {
+    let mut name = file_path.file_name().unwrap_or_default().to_owned();
+    name.push(META_SUFFIX);
+    file_path.with_file_name(name)
+}
+
+fn read_meta(meta_path: &Path) -> Option<serde_json::Value> {
+    let json_str = fs::read_to_string(meta_path).ok()?;
+    serde_json::from_str(&json_str).ok()
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Self {
+        Cache { dir: dir.to_owned() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn file_path(&self, component: Component, version: &str, file_name: &str) -> PathBuf {
+        self.dir.join(component.name()).join(sanitize(version)).join(file_name)
+    }
+
+    /// 读取缓存的文件，sha256和记录的不一致(或和 expected 不一致)时删除这个缓存
+    pub fn get(&self, component: Component, version: &str, file_name: &str, expected: Option<&str>) -> Option<Vec<u8>> {
+        let file_path = self.file_path(component, version, file_name);
+        let meta = read_meta(&meta_path(&file_path))?;
+        let data = fs::read(&file_path).ok()?;
+        let actual = sha256_hex(&data);
+        let recorded = meta["sha256"].as_str().unwrap_or_default();
+        let expected_ok = expected.map(|expected| expected.eq_ignore_ascii_case(&actual)).unwrap_or(true);
+        if !recorded.eq_ignore_ascii_case(&actual) || !expected_ok {
+            log::warn!("缓存的{file_name}({version})已损坏，将重新下载");
+            let _err = fs::remove_file(&file_path);
+            let _err = fs::remove_file(meta_path(&file_path));
+            return None;
+        }
+        log::info!("使用缓存的{} {version}: {file_name}", component.name());
+        Some(data)
+    }
+
+    pub fn put(&self, component: Component, version: &str, file_name: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = self.file_path(component, version, file_name);
+        fs::create_dir_all(file_path.parent().ok_or("can't get parent")?)?;
+        // 先写到临时文件，避免留下不完整的缓存
+        let tmp_path = file_path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &file_path)?;
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let meta = serde_json::json!({
+            "component": component.name(),
+            "version": version,
+            "file": file_name,
+            "size": data.len(),
+            "sha256": sha256_hex(data),
+            "created": created,
+        });
+        fs::write(meta_path(&file_path), serde_json::to_string_pretty(&meta)?)?;
+        Ok(())
+    }
+
+    /// 缓存中所有文件的记录
+    pub fn list(&self) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+        let mut metas = vec![];
+        if !self.dir.is_dir() {
+            return Ok(metas);
+        }
+        for component in fs::read_dir(&self.dir)? {
+            let component = component?;
+            if !component.file_type()?.is_dir() {
+                continue;
+            }
+            for version in fs::read_dir(component.path())? {
+                let version = version?;
+                if !version.file_type()?.is_dir() {
+                    continue;
+                }
+                for file in fs::read_dir(version.path())? {
+                    let file = file?;
+                    if file.file_name().to_string_lossy().ends_with(META_SUFFIX) {
+                        if let Some(meta) = read_meta(&file.path()) {
+                            metas.push(meta);
+                        }
+                    }
+                }
+            }
+        }
+        metas.sort_by_key(|meta| meta["created"].as_u64());
+        Ok(metas)
+    }
+
+    /// 最近缓存的版本，无法访问网络时使用
+    pub fn latest_version(&self, component: Component) -> Option<String> {
+        self.list()
+            .ok()?
+            .iter()
+            .rev()
+            .find(|meta| meta["component"] == component.name())
+            .and_then(|meta| meta["version"].as_str().map(|version| version.to_owned()))
+    }
+
+    /// 删除所有缓存，返回删除的文件数和大小
+    pub fn clean(&self) -> Result<(usize, u64), Box<dyn std::error::Error>> {
+        let metas = self.list()?;
+        let size = metas.iter().filter_map(|meta| meta["size"].as_u64()).sum();
+        if self.dir.is_dir() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok((metas.len(), size))
+    }
+}
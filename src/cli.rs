@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+
+/// 执行成功
+pub const EXIT_OK: i32 = 0;
+/// 执行失败
+pub const EXIT_FAILURE: i32 = 1;
+/// 命令行参数错误
+pub const EXIT_USAGE: i32 = 2;
+
+pub const USAGE: &str = r#"用法: llob_install [命令] [选项]
+
+命令:
+    install      安装LiteLoaderQQNT和LLOneBot(默认)
+    update       更新已经安装的LiteLoaderQQNT和LLOneBot
+    uninstall    卸载，撤销安装器所做的所有修改
+    restore [备份名]  还原安装前的备份，不指定备份名时列出所有备份
+    status       查看当前的安装状态
+    doctor       检查安装环境
+    bundle       下载最新版本的所有组件，生成离线包
+    cache [clean]  查看下载缓存，clean 清空缓存
+
+选项:
+    --qq-path <路径>    指定QQ的安装目录(或QQ.exe的路径)，不再自动查找
+    --bundle <路径>     从离线包(目录或zip)安装，不访问网络
+    -o, --output <路径> bundle命令生成的离线包的路径，默认为llob_bundle.zip
+    --pin <组件>=<版本> 固定组件的版本，组件为LiteLoaderQQNT、LLOneBot或QQNTFileVerifyPatch
+                        版本为tag，LiteLoaderQQNT还可以用commit:<sha>固定到某个提交，可以使用多次
+    --liteloader-channel <release|master>
+                        LiteLoaderQQNT使用最新的release(默认)，还是master分支的最新代码
+    --mirror <地址>     使用的github镜像，可以使用多次，{path}会被替换为github上的路径，
+                        没有{path}时作为前缀，例如 https://kkgithub.com
+                        也可以写成 download=<地址>,api=<地址> 同时替换api.github.com
+    --refresh-mirrors   忽略缓存，重新对镜像测速
+    --retries <次数>    所有镜像都下载失败后重试的次数，默认为2
+    --no-cache          不使用下载缓存
+    --ca-bundle <路径>  额外信任的根证书(pem格式)，用于公司内部的https代理
+    --insecure          不校验https证书，下载的文件可能被替换，不推荐使用
+    --require-checksum  没有可用的sha256时拒绝安装(LiteLoaderQQNT需要在配置文件中指定)
+    --versions <current|pending|all>
+                        修改versions目录下的哪些QQ版本: 正在使用的版本(默认)、
+                        正在使用的和等待更新的新版本、所有版本
+    --ignore-compat     QQ版本不在兼容性清单的范围内时仍然安装
+    --keep-data         卸载时保留LiteLoaderQQNT-main中的data目录(插件的配置)
+    --purge-backups     卸载时同时删除所有备份(默认保留)
+    -y, --yes           所有需要确认的地方都自动回答是
+    --no-wait           结束后直接退出，不等待按回车
+    -v, --verbose       输出调试日志
+    --json              在标准输出中输出json格式的结果，日志输出到标准错误
+    -h, --help          显示帮助"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Install,
+    Update,
+    Uninstall,
+    Restore,
+    Status,
+    Doctor,
+    Bundle,
+    Cache,
+    Help,
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Install => "install",
+            Command::Update => "update",
+            Command::Uninstall => "uninstall",
+            Command::Restore => "restore",
+            Command::Status => "status",
+            Command::Doctor => "doctor",
+            Command::Bundle => "bundle",
+            Command::Cache => "cache",
+            Command::Help => "help",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub qq_path: Option<PathBuf>,
+    pub snapshot: Option<String>,
+    pub cache_action: Option<String>,
+    pub bundle: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub pins: Vec<String>,
+    pub liteloader_channel: Option<String>,
+    pub mirrors: Vec<String>,
+    pub refresh_mirrors: bool,
+    pub retries: Option<u32>,
+    pub ca_bundle: Option<PathBuf>,
+    pub insecure: bool,
+    pub require_checksum: bool,
+    pub no_cache: bool,
+    pub ignore_compat: bool,
+    pub versions: Option<String>,
+    pub keep_data: bool,
+    pub purge_backups: bool,
+    pub yes: bool,
+    pub no_wait: bool,
+    pub json: bool,
+    pub verbose: bool,
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options {
+        command: Command::Install,
+        qq_path: None,
+        snapshot: None,
+        cache_action: None,
+        bundle: None,
+        output: None,
+        pins: vec![],
+        liteloader_channel: None,
+        mirrors: vec![],
+        refresh_mirrors: false,
+        retries: None,
+        ca_bundle: None,
+        insecure: false,
+        require_checksum: false,
+        no_cache: false,
+        ignore_compat: false,
+        versions: None,
+        keep_data: false,
+        purge_backups: false,
+        yes: false,
+        no_wait: false,
+        json: false,
+        verbose: false,
+    };
+    let mut command = None;
+    let mut help = false;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--qq-path" => {
+                let value = args.next().ok_or("--qq-path 需要一个路径")?;
+                opts.qq_path = Some(PathBuf::from(value));
+            }
+            "--bundle" => {
+                let value = args.next().ok_or("--bundle 需要一个路径")?;
+                opts.bundle = Some(PathBuf::from(value));
+            }
+            "-o" | "--output" => {
+                let value = args.next().ok_or("--output 需要一个路径")?;
+                opts.output = Some(PathBuf::from(value));
+            }
+            "--pin" => {
+                let value = args.next().ok_or("--pin 需要 组件=版本")?;
+                opts.pins.push(value);
+            }
+            "--liteloader-channel" => {
+                let value = args.next().ok_or("--liteloader-channel 需要 release 或 master")?;
+                opts.liteloader_channel = Some(value);
+            }
+            "--mirror" => {
+                let value = args.next().ok_or("--mirror 需要一个地址")?;
+                opts.mirrors.push(value);
+            }
+            "--refresh-mirrors" => opts.refresh_mirrors = true,
+            "--retries" => {
+                let value = args.next().ok_or("--retries 需要一个次数")?;
+                opts.retries = Some(value.parse().map_err(|_| format!("--retries 需要一个次数: {value}"))?);
+            }
+            "--ca-bundle" => {
+                let value = args.next().ok_or("--ca-bundle 需要一个路径")?;
+                opts.ca_bundle = Some(PathBuf::from(value));
+            }
+            "--insecure" => opts.insecure = true,
+            "--require-checksum" => opts.require_checksum = true,
+            "--no-cache" => opts.no_cache = true,
+            "--ignore-compat" => opts.ignore_compat = true,
+            "--versions" => {
+                let value = args.next().ok_or("--versions 需要 current、pending 或 all")?;
+                if !["current", "pending", "all"].contains(&value.as_str()) {
+                    return Err(format!("--versions 需要 current、pending 或 all: {value}"));
+                }
+                opts.versions = Some(value);
+            }
+            "--keep-data" => opts.keep_data = true,
+            "--purge-backups" => opts.purge_backups = true,
+            "-y" | "--yes" => opts.yes = true,
+            "--no-wait" => opts.no_wait = true,
+            "--json" => opts.json = true,
+            "-v" | "--verbose" => opts.verbose = true,
+            "-h" | "--help" => help = true,
+            _ if arg.starts_with('-') => return Err(format!("未知的选项: {arg}")),
+            _ => {
+                if command == Some(Command::Restore) && opts.snapshot.is_none() {
+                    opts.snapshot = Some(arg);
+                    continue;
+                }
+                if command == Some(Command::Cache) && opts.cache_action.is_none() {
+                    opts.cache_action = Some(arg);
+                    continue;
+                }
+                if command.is_some() {
+                    return Err(format!("多余的参数: {arg}"));
+                }
+                command = Some(match arg.as_str() {
+                    "install" => Command::Install,
+                    "update" => Command::Update,
+                    "uninstall" => Command::Uninstall,
+                    "restore" => Command::Restore,
+                    "status" => Command::Status,
+                    "doctor" => Command::Doctor,
+                    "bundle" => Command::Bundle,
+                    "cache" => Command::Cache,
+                    "help" => Command::Help,
+                    _ => return Err(format!("未知的命令: {arg}")),
+                });
+            }
+        }
+    }
+    if help {
+        opts.command = Command::Help;
+    } else if let Some(command) = command {
+        opts.command = command;
+    }
+    Ok(opts)
+}
@@ -0,0 +1,112 @@
+use std::{cmp::Ordering, fmt, path::Path, sync::Arc};
+
+use crate::{platform::QQLayout, release::glob_match, source::Component};
+
+/// 内置的兼容性清单，配置文件中的 compat_manifest 可以替换它
+/// 9.9.15之后的目录结构要求构建号不低于28060，旧版QQ的版本范围没有内置，需要用户自己的清单
+const BUILTIN_MANIFEST: &str = r#"{
+    "LiteLoaderQQNT": [{"tag": "*", "layout": "current", "min_build": 28060}],
+    "LLOneBot": [{"tag": "*", "layout": "current", "min_build": 28060}]
+}"#;
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// QQ的版本号，如 9.9.15-28060，linux版为 3.2.12-28060
+/// 各平台的版本号不同，但构建号是统一递增的，所以先按构建号比较
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QQVersion {
+    pub numbers: Vec<u32>,
+    pub build: u32,
+}
+
+impl QQVersion {
+    pub fn parse(version: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (numbers, build) = version.trim().rsplit_once('-').ok_or(format!("无法识别的QQ版本号: {version}"))?;
+        let build = build.parse().map_err(|_| format!("无法识别的QQ版本号: {version}"))?;
+        let numbers = numbers
+            .split('.')
+            .map(|number| number.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("无法识别的QQ版本号: {version}"))?;
+        Ok(QQVersion { numbers, build })
+    }
+}
+
+impl Ord for QQVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.build.cmp(&other.build).then_with(|| self.numbers.cmp(&other.numbers))
+    }
+}
+
+impl PartialOrd for QQVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for QQVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numbers: Vec<String> = self.numbers.iter().map(|number| number.to_string()).collect();
+        write!(f, "{}-{}", numbers.join("."), self.build)
+    }
+}
+
+/// 兼容性清单，按组件列出每个版本支持的QQ构建号范围
+/// 格式为 {"LLOneBot": [{"tag": "v4.*", "min_build": 28060, "max_build": 29000}]}
+/// tag可以使用*和?，按顺序使用第一个匹配的项，min_build和max_build都可以不填
+/// layout 为 current 或 legacy 时这一项只用于这种目录结构的QQ
+pub struct CompatManifest {
+    json: serde_json::Value,
+    builtin: bool,
+}
+
+impl CompatManifest {
+    pub fn builtin() -> Self {
+        CompatManifest {
+            json: serde_json::from_str(BUILTIN_MANIFEST).unwrap_or_default(),
+            builtin: true,
+        }
+    }
+
+    /// location 可以是网址或本地文件
+    pub fn load(rt_ptr: Arc<tokio::runtime::Runtime>, location: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bin = if is_url(location) {
+            crate::http_post(rt_ptr, location, None)?
+        } else {
+            std::fs::read(Path::new(location))?
+        };
+        let json: serde_json::Value = serde_json::from_slice(&bin)?;
+        if !json.is_object() {
+            return Err("格式错误".into());
+        }
+        log::info!("使用兼容性清单{location}");
+        Ok(CompatManifest { json, builtin: false })
+    }
+
+    /// 是否为内置的清单
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// 检查组件的这个版本是否支持这个QQ，不支持时返回原因
+    pub fn check(&self, component: Component, tag: &str, qq_version: &QQVersion, layout: QQLayout) -> Option<String> {
+        let entry = self.json[component.name()].as_array()?.iter().find(|entry| {
+            let tag_matches = entry["tag"].as_str().map(|pattern| glob_match(pattern, tag)).unwrap_or(false);
+            let layout_matches = entry["layout"].as_str().map(|name| name == layout.name()).unwrap_or(true);
+            tag_matches && layout_matches
+        })?;
+        if let Some(min_build) = entry["min_build"].as_u64() {
+            if (qq_version.build as u64) < min_build {
+                return Some(format!("{} {tag}需要QQ构建号不低于{min_build}，当前QQ为{qq_version}", component.name()));
+            }
+        }
+        if let Some(max_build) = entry["max_build"].as_u64() {
+            if (qq_version.build as u64) > max_build {
+                return Some(format!("{} {tag}只支持到QQ构建号{max_build}，当前QQ为{qq_version}", component.name()));
+            }
+        }
+        None
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    collections::HashMap,
     fs::{self},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
 };
@@ -10,55 +11,246 @@ use path_clean::PathClean;
 use reqwest::header::{HeaderName, HeaderValue};
 use time::UtcOffset;
 
-use std::mem::{size_of, zeroed};
-use std::ptr::null_mut;
-use winapi::um::handleapi::CloseHandle;
-use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
-use winapi::um::securitybaseapi::GetTokenInformation;
-use winapi::um::winnt::{TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+mod backup;
+mod bundle;
+mod cache;
+mod cli;
+mod compat;
+mod mirror;
+mod net;
+mod platform;
+mod release;
+mod source;
+mod status;
+mod transaction;
 
-fn get_apath(path: &PathBuf) -> PathBuf {
-    let apath;
-    if path.is_absolute() {
-        apath = path.clean();
-    } else {
-        apath = std::env::current_dir().unwrap().join(path).clean();
-    }
-    apath
-}
+use backup::Backup;
+use cache::Cache;
+use cli::{Command, Options};
+use compat::{CompatManifest, QQVersion};
+use mirror::Mirror;
+use platform::{Injection, Platform, QQLayout};
+use release::BackendConfig;
+use source::{AssetPatterns, BundleSource, Checksums, Component, NetworkOptions, NetworkSource, Pin, Pins, Source};
+use transaction::{Step, StepError};
+
+/// LiteLoaderQQNT 在用户目录下的目录名
+const LITELOADER_DIR_NAME: &str = "LiteLoaderQQNT-main";
+/// 安装前 app_launcher/index.js 的内容
+const ORIGINAL_INDEX_JS: &str = "require('../major.node').load('internal_index', module);\r\n";
 
-fn get_qq_path_by_reg() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let hkcu = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE);
-    let qq_setting;
-    if let Ok(val) = hkcu.open_subkey(r#"Software\Microsoft\Windows\CurrentVersion\Uninstall\QQ"#) {
-        qq_setting = val;
+fn get_apath(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.clean()
     } else {
-        qq_setting = hkcu.open_subkey(r#"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall\QQ"#)?;
+        std::env::current_dir().unwrap().join(path).clean()
     }
-    let qq_path: String = qq_setting.get_value("UninstallString")?;
-    let q = PathBuf::from_str(&qq_path)?
-        .parent()
-        .ok_or("can't find qq path")?
-        .to_owned();
-    Ok(q)
 }
 
-fn get_qq_path_by_current_exe_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+fn get_qq_path_by_current_exe_path(qq_exe_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let current_exe_path = std::env::current_exe()?;
     let current_path = current_exe_path.parent().ok_or("can't find current path")?;
-    let qq_path = current_path.join("QQ.exe");
+    let qq_path = current_path.join(qq_exe_name);
     if qq_path.is_file() {
         return Ok(current_path.to_path_buf());
     }
     Err("can't find qq.exe on current path".into())
 }
 
-fn get_qq_path_by_cfg() -> Result<PathBuf, Box<dyn std::error::Error>> {
+/// 读取 llob_install.exe 所在目录下的 llob_install.json，没有配置文件时返回Null
+fn read_cfg() -> Result<serde_json::Value, Box<dyn std::error::Error>> {
     let current_exe_path = std::env::current_exe()?;
     let current_path = current_exe_path.parent().ok_or("can't find current path")?;
     let cfg_file = current_path.join("llob_install.json");
-    let json_str = fs::read_to_string(cfg_file)?;
-    let json: serde_json::Value = serde_json::from_str(&json_str)?;
+    if !cfg_file.is_file() {
+        return Ok(serde_json::Value::Null);
+    }
+    let json_str = fs::read_to_string(&cfg_file)?;
+    let json: serde_json::Value = serde_json::from_str(&json_str).map_err(|err| format!("解析{cfg_file:?}失败: {err}"))?;
+    Ok(json)
+}
+
+/// 固定的版本，命令行中的 --pin 优先于配置文件中的 pin
+fn get_pins(opts: &Options) -> Result<Pins, Box<dyn std::error::Error>> {
+    let mut pins = Pins::new();
+    let cfg = read_cfg()?;
+    let mut raw_pins = vec![];
+    if let Some(cfg_pins) = cfg["pin"].as_object() {
+        for (name, value) in cfg_pins {
+            let value = value.as_str().ok_or(format!("llob_install.json中pin.{name}必须是字符串"))?;
+            raw_pins.push((name.clone(), value.to_owned()));
+        }
+    }
+    for pin in &opts.pins {
+        let (name, value) = pin.split_once('=').ok_or(format!("--pin 的格式应为 组件=版本: {pin}"))?;
+        raw_pins.push((name.to_owned(), value.to_owned()));
+    }
+    for (name, value) in raw_pins {
+        let component = Component::from_name(&name).ok_or(format!("未知的组件: {name}"))?;
+        let pin = Pin::parse(&value);
+        if component != Component::LiteLoader && matches!(pin, Pin::Commit(_)) {
+            return Err(format!("{}只能固定到tag", component.name()).into());
+        }
+        log::info!("{}固定为{}", component.name(), value);
+        pins.insert(component, pin);
+    }
+
+    // LiteLoaderQQNT 默认使用最新的release，可以选择跟踪master分支
+    let channel = match &opts.liteloader_channel {
+        Some(channel) => Some(channel.as_str()),
+        None => cfg["liteloader_channel"].as_str(),
+    };
+    match channel {
+        None | Some("release") => {}
+        Some("master") => {
+            match pins.entry(Component::LiteLoader) {
+                std::collections::hash_map::Entry::Occupied(_) => log::warn!("LiteLoaderQQNT已经固定了版本，忽略master通道"),
+                std::collections::hash_map::Entry::Vacant(entry) => {
+                    entry.insert(Pin::Branch("master".to_owned()));
+                }
+            }
+        }
+        Some(channel) => return Err(format!("未知的LiteLoaderQQNT通道: {channel}，只能是release或master").into()),
+    }
+    Ok(pins)
+}
+
+/// 配置文件中 keep_backups 指定的保留的备份数
+fn get_keep_backups() -> Result<usize, Box<dyn std::error::Error>> {
+    let cfg = read_cfg()?;
+    Ok(match &cfg["keep_backups"] {
+        serde_json::Value::Null => backup::DEFAULT_KEEP_BACKUPS,
+        value => value.as_u64().ok_or("llob_install.json中keep_backups必须是数字")? as usize,
+    })
+}
+
+/// 配置文件中 sha256 字段指定的校验值，--require-checksum 或 require_checksum 要求每个文件都有校验值
+fn get_checksums(opts: &Options) -> Result<Checksums, Box<dyn std::error::Error>> {
+    let cfg = read_cfg()?;
+    let mut checksums = Checksums {
+        required: opts.require_checksum || cfg["require_checksum"] == true,
+        ..Default::default()
+    };
+    if let Some(cfg_checksums) = cfg["sha256"].as_object() {
+        for (file_name, sha256) in cfg_checksums {
+            let sha256 = sha256.as_str().ok_or(format!("llob_install.json中sha256.{file_name}必须是字符串"))?;
+            checksums.files.insert(file_name.clone(), sha256.to_owned());
+        }
+    }
+    Ok(checksums)
+}
+
+/// 配置文件中 compat_manifest 指定的兼容性清单，没有指定时使用内置的清单
+/// 指定的清单读不到时拒绝安装，否则其中的版本范围会被悄悄忽略，使用--ignore-compat时改用内置的清单
+fn get_compat_manifest(rt_ptr: Arc<tokio::runtime::Runtime>, cfg: &serde_json::Value, opts: &Options) -> Result<CompatManifest, Box<dyn std::error::Error>> {
+    let Some(location) = cfg["compat_manifest"].as_str() else {
+        return Ok(CompatManifest::builtin());
+    };
+    // 从离线包安装时不访问网络
+    let ret = if opts.bundle.is_some() && compat::is_url(location) {
+        Err("从离线包安装时不访问网络，请使用本地文件".into())
+    } else {
+        CompatManifest::load(rt_ptr, location)
+    };
+    match ret {
+        Ok(manifest) => Ok(manifest),
+        Err(err) if opts.ignore_compat => {
+            log::warn!("无法读取兼容性清单{location}: {err}，已使用--ignore-compat，改用内置的清单");
+            Ok(CompatManifest::builtin())
+        }
+        Err(err) => Err(format!("无法读取兼容性清单{location}: {err}，确定要安装请使用--ignore-compat").into()),
+    }
+}
+
+/// github镜像，命令行中的 --mirror 会替换配置文件中的 mirrors
+fn get_mirrors(opts: &Options) -> Result<Vec<Mirror>, Box<dyn std::error::Error>> {
+    if !opts.mirrors.is_empty() {
+        return opts.mirrors.iter().map(|mirror| Mirror::from_arg(mirror)).collect();
+    }
+    let cfg = read_cfg()?;
+    match cfg["mirrors"].as_array() {
+        Some(cfg_mirrors) if !cfg_mirrors.is_empty() => cfg_mirrors.iter().map(Mirror::from_json).collect(),
+        _ => mirror::default_mirrors(),
+    }
+}
+
+/// 环境变量中的 GITHUB_TOKEN/GH_TOKEN 优先于配置文件中的 github_token，空值会被跳过
+fn get_github_token(cfg: &serde_json::Value) -> Option<String> {
+    ["GITHUB_TOKEN", "GH_TOKEN"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|token| !token.is_empty()))
+        .or_else(|| cfg["github_token"].as_str().filter(|token| !token.is_empty()).map(|token| token.to_owned()))
+}
+
+/// 配置文件中 release_sources 指定的每个组件的版本来源
+fn get_release_sources(cfg: &serde_json::Value) -> Result<HashMap<Component, BackendConfig>, Box<dyn std::error::Error>> {
+    let mut release_sources = HashMap::new();
+    if let Some(cfg_sources) = cfg["release_sources"].as_object() {
+        for (name, value) in cfg_sources {
+            let component = Component::from_name(name).ok_or(format!("未知的组件: {name}"))?;
+            release_sources.insert(component, BackendConfig::from_json(value)?);
+        }
+    }
+    Ok(release_sources)
+}
+
+/// 配置文件中 asset_patterns 指定的文件名模式，值可以是字符串或字符串数组
+fn get_asset_patterns(cfg: &serde_json::Value) -> Result<AssetPatterns, Box<dyn std::error::Error>> {
+    let mut asset_patterns = AssetPatterns::new();
+    if let Some(cfg_patterns) = cfg["asset_patterns"].as_object() {
+        for (file_name, value) in cfg_patterns {
+            let patterns = match value {
+                serde_json::Value::String(pattern) => vec![pattern.to_owned()],
+                serde_json::Value::Array(patterns) => patterns
+                    .iter()
+                    .map(|pattern| pattern.as_str().map(|pattern| pattern.to_owned()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(format!("asset_patterns中{file_name}的格式错误"))?,
+                _ => return Err(format!("asset_patterns中{file_name}的格式错误").into()),
+            };
+            asset_patterns.insert(file_name.to_owned(), patterns);
+        }
+    }
+    Ok(asset_patterns)
+}
+
+/// 代理、镜像和重试的设置，命令行优先于配置文件
+fn get_network_options(platform: &dyn Platform, opts: &Options) -> Result<NetworkOptions, Box<dyn std::error::Error>> {
+    let cfg = read_cfg()?;
+    let ca_certs = match opts.ca_bundle.as_deref().or(cfg["ca_bundle"].as_str().map(Path::new)) {
+        Some(ca_bundle) => net::load_ca_bundle(&get_apath(ca_bundle))?,
+        None => vec![],
+    };
+    net::configure(net::NetSettings {
+        proxy: net::ProxySetting::parse(cfg["proxy"].as_str())?,
+        ca_certs,
+        insecure: opts.insecure,
+        github_token: get_github_token(&cfg),
+    });
+    let retries = match opts.retries {
+        Some(retries) => retries,
+        None => cfg["retries"].as_u64().unwrap_or(2) as u32,
+    };
+    let backoff_ms = cfg["retry_backoff_ms"].as_u64().unwrap_or(1000);
+    Ok(NetworkOptions {
+        mirrors: get_mirrors(opts)?,
+        refresh_mirrors: opts.refresh_mirrors,
+        retries,
+        backoff: std::time::Duration::from_millis(backoff_ms),
+        release_sources: get_release_sources(&cfg)?,
+        asset_patterns: get_asset_patterns(&cfg)?,
+        mirror_cache: platform.cache_dir()?.join(mirror::CACHE_NAME),
+        download_dir: platform.cache_dir()?.join(source::DOWNLOAD_DIR_NAME),
+        cache: if opts.no_cache || cfg["cache"] == false {
+            None
+        } else {
+            Some(Cache::new(&platform.cache_dir()?))
+        },
+    })
+}
+
+fn get_qq_path_by_cfg() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let json = read_cfg()?;
     let qq_path_str = json["qq_exe_path"]
         .as_str()
         .ok_or("failed to get qq_exe_path")?;
@@ -73,105 +265,58 @@ fn get_qq_path_by_cfg() -> Result<PathBuf, Box<dyn std::error::Error>> {
     Err("can't find qq.exe llob_install.json".into())
 }
 
-fn get_qq_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+fn get_qq_path(platform: &dyn Platform) -> Result<PathBuf, Box<dyn std::error::Error>> {
     // 先看配置文件
     if let Ok(qq_path) = get_qq_path_by_cfg() {
         log::info!("从配置文件获取到QQ.exe");
         return Ok(qq_path);
     }
     // 再看当前目录
-    if let Ok(qq_path) = get_qq_path_by_current_exe_path() {
+    if let Ok(qq_path) = get_qq_path_by_current_exe_path(platform.qq_exe_name()) {
         log::info!("从当前位置获取到QQ.exe");
         return Ok(qq_path);
     }
-    // 再看注册表
-    if let Ok(qq_path) = get_qq_path_by_reg() {
-        log::info!("从注册表获取到QQ.exe");
+    // 再看系统(注册表、默认安装位置)
+    if let Ok(qq_path) = platform.find_qq_path() {
         return Ok(qq_path);
     }
     Err("can't find qq path".into())
 }
 
-fn is_qq_run(qq_path:&PathBuf) -> Result<bool, Box<dyn std::error::Error>>  {
-    let system = sysinfo::System::new_all();
-    let process_name = "QQ.exe";
-    if let Some(process) = system.processes_by_name(process_name).next() {
-        let process_exe_path = process.exe().ok_or("can't get process exe path")?;
-        if let Some(process_path) = process_exe_path.parent() {
-            if process_path == qq_path {
-                return Ok(true)
-            }
-        }
-    } 
-    Ok(false)
-}
-
+/// 访问github api，返回json的内容
 fn http_post(rt_ptr: Arc<tokio::runtime::Runtime>, url: &str, user_agent: Option<&str>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let bin = rt_ptr.block_on(async {
-        let client = reqwest::Client::builder()
-            .danger_accept_invalid_certs(true)
-            .no_proxy()
-            .build()
-            .unwrap();
+        let client = net::client()?;
         let mut req = client
             .get(url)
             .body(reqwest::Body::from(vec![]))
             .build()
-            .unwrap();
+            .map_err(|err| format!("无法访问{url}: {err}"))?;
         if let Some(ua) = user_agent {
             req.headers_mut().append(
                 HeaderName::from_str("User-Agent").unwrap(),
                 HeaderValue::from_str(ua).unwrap(),
             );
         }
-        let ret = client.execute(req).await;
-        if ret.is_err() {
-            log::error!("Failed to download file{:?}", ret.err().unwrap());
-            return Err("Failed to download file".into());
-        }
-        let ret = ret.unwrap();
-        let bin = ret.bytes().await;
-        if bin.is_err() {
-            log::error!("Failed to download file{:?}", bin.err().unwrap());
-            return Err("Failed to download file".into());
+        if let Some(token) = net::github_token_for(url) {
+            req.headers_mut().append(
+                reqwest::header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {token}"))?,
+            );
         }
-        let bin = bin.unwrap();
+        let ret = client.execute(req).await.map_err(|err| {
+            log::debug!("Failed to download file{err:?}");
+            format!("无法访问{url}: {err}")
+        })?;
+        let ret = net::check_response(ret, net::Expect::Json).await?;
+        let bin = ret.bytes().await?;
+        net::check_body(&bin, net::Expect::Json)?;
         Ok(bin.to_vec())
     });
     bin
 }
 
-fn is_admin() -> Result<bool, Box<dyn std::error::Error>> {
-    let mut token: winapi::um::winnt::HANDLE = null_mut();
-    let process = unsafe { GetCurrentProcess() };
-
-    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } != 0 {
-        let mut elevation: TOKEN_ELEVATION = unsafe { zeroed() };
-        let mut ret_length = 0;
-
-        let success = unsafe {
-            GetTokenInformation(
-                token,
-                TokenElevation,
-                &mut elevation as *mut _ as winapi::shared::minwindef::LPVOID,
-                size_of::<TOKEN_ELEVATION>() as u32,
-                &mut ret_length,
-            )
-        };
-
-        unsafe { CloseHandle(token) };
-
-        if success != 0 && elevation.TokenIsElevated != 0 {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
-    } else {
-        Ok(false)
-    }
-}
-
-fn init_log() {
+fn init_log(json: bool, verbose: bool) {
     // 初始化日志
     let format = "[year]-[month]-[day] [hour]:[minute]:[second]";
 
@@ -190,76 +335,30 @@ fn init_log() {
             format_description::parse(format).unwrap(),
         ))
         .with_ansi(false)
-        .with_max_level(tracing::Level::INFO)
+        // 调试日志只输出安装器自己的，不输出依赖库的
+        .with_env_filter(tracing_subscriber::EnvFilter::new(if verbose { "info,llob_install=debug" } else { "info" }))
+        // 输出json时，标准输出只留给json
+        .with_writer(move || -> Box<dyn std::io::Write> {
+            if json {
+                Box::new(std::io::stderr())
+            } else {
+                Box::new(std::io::stdout())
+            }
+        })
         .init();
 }
 
-fn app_exit() -> ! {
-    loop {
-        let time_struct = core::time::Duration::from_millis(500);
-        std::thread::sleep(time_struct);
-    }
-}
-
-fn is_x86_64(exe_data: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
-    use goblin::Object;
-    match Object::parse(exe_data)? {
-        Object::PE(pe) => Ok(pe.is_64),
-        _ => Err("File is not a Windows PE file.".into()),
-    }
-}
-
-fn iswin32(qq_exe_path: &PathBuf) -> Result<bool, Box<dyn std::error::Error>> {
-    let content = std::fs::read(qq_exe_path)?;
-    if is_x86_64(&content)? {
-        return Ok(false);
-    }
-    Ok(true)
-}
-
-pub async fn github_proxy() -> Option<String> {
-    let urls_to_test = [
-        "https://kkgithub.com",
-        "https://dgithub.xyz",
-        "https://gh.jiasu.in/https://github.com",
-        "https://github.com",
-    ];
-    let (tx, mut rx) = tokio::sync::mpsc::channel(urls_to_test.len() + 1);
-    for url in urls_to_test {
-        let tx = tx.clone();
-        tokio::spawn(async move {
-            let client = reqwest::Client::builder()
-                .danger_accept_invalid_certs(true)
-                .no_proxy()
-                .build()
-                .unwrap();
-            let uri = reqwest::Url::from_str(&(url.to_owned() + "/LiteLoaderQQNT/QQNTFileVerifyPatch/releases/download/DllHijack_1.0.8/dbghelp_x64.dll")).unwrap();
-            let req = client.get(uri).build().unwrap();
-            if let Ok(ret) = client.execute(req).await {
-                if ret.status() == reqwest::StatusCode::OK {
-                    if let Ok(bin) = ret.bytes().await {
-                        if bin.starts_with(&[b'M', b'Z']) {
-                            let _err = tx.send(url).await;
-                        }
-                    }
-                }
-            };
-        });
-    }
-    tokio::spawn(async move {
-        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-        let _err = tx.send("timeout").await;
-    });
-    let ret = rx.recv().await;
-    if let Some(r) = ret {
-        if r != "timeout" {
-            return Some(r.to_owned());
-        }
+fn app_exit(code: i32, wait: bool) -> ! {
+    // 双击运行时窗口会直接关闭，所以默认等待用户按回车
+    if wait {
+        log::info!("按回车键退出...");
+        let mut line = String::new();
+        let _err = std::io::stdin().read_line(&mut line);
     }
-    None
+    std::process::exit(code);
 }
 
-fn fix_index_js(index_js_path:&PathBuf,userdir:&PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+fn fix_index_js(index_js_path:&Path,userdir:&Path) -> Result<(), Box<dyn std::error::Error>> {
     let mut to_write = r#"const fs = require("fs");
 const path = require("path");
 const package_path = path.join(process.resourcesPath, "app/package.json");
@@ -269,9 +368,8 @@ fs.writeFileSync(package_path, JSON.stringify(package, null, 4), "utf-8");
 "#.to_owned();
     to_write.push_str(&("require(String.raw`".to_owned()
     + &userdir
-        .join("LiteLoaderQQNT-main")
+        .join(LITELOADER_DIR_NAME)
         .to_string_lossy()
-        .to_string()
     + "`);\r\n"));
     to_write.push_str("require('../major.node').load('internal_index', module);\r\n");
 
@@ -287,10 +385,56 @@ fs.writeFileSync(package_path, JSON.stringify(package, null, 4), "utf-8");
     Ok(())
 }
 
-fn extrat(from: &PathBuf, to: &PathBuf, flag: bool) -> Result<(), Box<dyn std::error::Error>> {
+/// 没有文件校验的平台加在index.js最前面的一行
+fn liteloader_require(userdir:&Path) -> String {
+    format!("require(String.raw`{}`);\n", userdir.join(LITELOADER_DIR_NAME).to_string_lossy())
+}
+
+/// index.js是在最前面加了一行require注入的时，返回去掉这一行之后的内容
+fn strip_prepended(index_js:&str) -> Option<&str> {
+    let (first, rest) = index_js.split_once('\n')?;
+    let prepended = first.starts_with("require(String.raw`") && first.contains(LITELOADER_DIR_NAME) && !rest.contains(LITELOADER_DIR_NAME);
+    prepended.then_some(rest)
+}
+
+/// 在QQ原来的index.js最前面加载LiteLoaderQQNT，package.json保持不变
+fn prepend_index_js(index_js_path:&Path,userdir:&Path,backup_root:&Path) -> Result<(), Box<dyn std::error::Error>> {
+    let index_js = fs::read_to_string(index_js_path)?;
+    let original = match strip_prepended(&index_js) {
+        Some(rest) => rest.to_owned(),
+        // 之前用替换index.js的方式注入过，先找回原来的内容
+        None if index_js.contains(LITELOADER_DIR_NAME) => original_index_js(index_js_path, backup_root),
+        None => index_js,
+    };
+    fs::write(index_js_path, liteloader_require(userdir) + &original)?;
+    Ok(())
+}
+
+/// 旧版QQ不校验文件，新建一个脚本先加载LiteLoaderQQNT，再加载QQ原来的入口
+fn fix_legacy_launcher(launcher_path:&Path,userdir:&Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut to_write = "require(String.raw`".to_owned()
+        + &userdir.join(LITELOADER_DIR_NAME).to_string_lossy()
+        + "`);\r\n";
+    to_write.push_str("require('./index.js');\r\n");
+    fs::write(launcher_path, to_write)?;
+    Ok(())
+}
+
+/// zip中的所有文件都在同一个目录下，如github的源码包 LiteLoaderQQNT-1.2.3/...
+fn has_single_root<R: std::io::Read + std::io::Seek>(archive: &zip::ZipArchive<R>) -> bool {
+    let mut roots = archive.file_names().map(|name| name.split_once('/').map(|(root, _)| root));
+    match roots.next() {
+        Some(Some(first)) => roots.all(|root| root == Some(first)),
+        _ => false,
+    }
+}
+
+/// flag 为true时去掉zip中最外层的目录，没有这一层时直接解压
+fn extrat(from: &Path, to: &Path, flag: bool) -> Result<(), Box<dyn std::error::Error>> {
     let file = std::fs::File::open(from)?;
 
     let mut archive = zip::ZipArchive::new(file)?;
+    let flag = flag && has_single_root(&archive);
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
         let outpath = match file.enclosed_name() {
@@ -361,27 +505,57 @@ fn extrat(from: &PathBuf, to: &PathBuf, flag: bool) -> Result<(), Box<dyn std::e
     Ok(())
 }
 
-fn get_qq_version(qqpath:&PathBuf) -> Result<String, Box<dyn std::error::Error>> {
-    let config_json_path = qqpath.join("versions").join("config.json");
-    let config_str = fs::read_to_string(config_json_path)?;
-    let config_json:serde_json::Value = serde_json::from_str(&config_str)?;
-    let cur_version = config_json["curVersion"].as_str().ok_or("解析config.json失败")?;
-    return Ok(cur_version.to_owned());
-}
-
 fn main() {
-    if let Err(e) = mymain() {
-        log::error!("{e:?}");
-        app_exit();
+    let opts = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            std::process::exit(cli::EXIT_USAGE);
+        }
+    };
+    if opts.command == Command::Help {
+        println!("{}", cli::USAGE);
+        std::process::exit(cli::EXIT_OK);
     }
-    app_exit();
+
+    init_log(opts.json, opts.verbose);
+
+    let code = match mymain(&opts) {
+        Ok(mut report) => {
+            report["command"] = serde_json::json!(opts.command.name());
+            let ok = report["ok"].as_bool().unwrap_or(true);
+            report["ok"] = serde_json::json!(ok);
+            if opts.json {
+                println!("{report}");
+            }
+            if ok {
+                cli::EXIT_OK
+            } else {
+                cli::EXIT_FAILURE
+            }
+        }
+        Err(e) => {
+            log::error!("{e}");
+            if opts.json {
+                let failed_step = e.downcast_ref::<StepError>().map(|err| err.step);
+                println!("{}", serde_json::json!({
+                    "command": opts.command.name(),
+                    "ok": false,
+                    "error": e.to_string(),
+                    "failed_step": failed_step,
+                }));
+            }
+            cli::EXIT_FAILURE
+        }
+    };
+    app_exit(code, !opts.no_wait);
 }
 
-fn fix_package_json(package_json_path:&PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+fn fix_package_json(package_json_path:&Path,layout:QQLayout) -> Result<(), Box<dyn std::error::Error>> {
     let json_str = fs::read_to_string(package_json_path)?;
     let mut json:serde_json::Value = serde_json::from_str(&json_str)?;
     let json_main = json.get_mut("main").ok_or("没有在package.json中找到main字段")?;
-    *json_main = serde_json::json!("./app_launcher/index.js");
+    *json_main = serde_json::json!(layout.patched_main());
     fs::write(
         package_json_path,
         serde_json::to_string_pretty(&json)?,
@@ -389,203 +563,859 @@ fn fix_package_json(package_json_path:&PathBuf) -> Result<(), Box<dyn std::error
     Ok(())
 }
 
-fn mymain() -> Result<(), Box<dyn std::error::Error>> {
-    let rt_ptr: Arc<tokio::runtime::Runtime> = Arc::new(tokio::runtime::Runtime::new().unwrap());
+/// QQ原来的package.json中的main，优先使用最早的备份中的值，没有备份时使用这种目录结构原来的值
+fn original_main(package_json_path:&Path,layout:QQLayout,backup_root:&Path) -> String {
+    let original = Backup::original_file(backup_root, package_json_path)
+        .and_then(|bin| serde_json::from_slice::<serde_json::Value>(&bin).ok())
+        .and_then(|json| json["main"].as_str().map(|main| main.to_owned()));
+    original.unwrap_or_else(|| {
+        log::warn!("没有找到{package_json_path:?}的备份，使用默认的main");
+        layout.original_main().to_owned()
+    })
+}
 
-    init_log();
+/// 只还原main，QQ更新后package.json中的其它内容(如版本号)可能已经和备份中的不同
+fn restore_package_json(package_json_path:&Path,original_main:&str) -> Result<(), Box<dyn std::error::Error>> {
+    let json_str = fs::read_to_string(package_json_path)?;
+    let mut json:serde_json::Value = serde_json::from_str(&json_str)?;
+    let json_main = json.get_mut("main").ok_or("没有在package.json中找到main字段")?;
+    *json_main = serde_json::json!(original_main);
+    fs::write(
+        package_json_path,
+        serde_json::to_string_pretty(&json)?,
+    )?;
+    Ok(())
+}
+
+fn is_package_json_patched(package_json_path:&Path,layout:QQLayout) -> Result<bool, Box<dyn std::error::Error>> {
+    let json_str = fs::read_to_string(package_json_path)?;
+    let json:serde_json::Value = serde_json::from_str(&json_str)?;
+    Ok(json["main"] == layout.patched_main())
+}
+
+/// QQ原来的index.js，优先使用最早的备份中的内容，没有备份(或备份中的已经被修改过)时使用内置的内容
+fn original_index_js(index_js_path:&Path,backup_root:&Path) -> String {
+    let original = Backup::original_file(backup_root, index_js_path)
+        .map(|bin| String::from_utf8_lossy(&bin).into_owned())
+        .filter(|index_js| !index_js.contains(LITELOADER_DIR_NAME));
+    original.unwrap_or_else(|| {
+        log::warn!("没有找到{index_js_path:?}的备份，使用内置的index.js");
+        ORIGINAL_INDEX_JS.to_owned()
+    })
+}
+
+fn restore_index_js(index_js_path:&Path,layout:QQLayout,backup_root:&Path) -> Result<(), Box<dyn std::error::Error>> {
+    match layout {
+        QQLayout::Current => {
+            let index_js = fs::read_to_string(index_js_path)?;
+            let original = match strip_prepended(&index_js) {
+                Some(rest) => rest.to_owned(),
+                None => original_index_js(index_js_path, backup_root),
+            };
+            fs::write(index_js_path, original)?;
+        }
+        // 旧版的脚本是安装器新建的，直接删除
+        QQLayout::Legacy => fs::remove_file(index_js_path)?,
+    }
+    Ok(())
+}
+
+fn mymain(opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let rt_ptr: Arc<tokio::runtime::Runtime> = Arc::new(tokio::runtime::Runtime::new().unwrap());
 
     log::info!("欢迎使用LLOB安装器0.0.10 by super1207");
 
-    if let Ok(_) = std::env::var("LITELOADERQQNT_PROFILE") {
-        log::error!("检测到您的环境变量中存在LITELOADERQQNT_PROFILE，你可能已经手动安装过LiteLoaderQQNT，程序终止！");
-        app_exit();
+    let platform = platform::current();
+    let ret = run_command(&*platform, rt_ptr, opts);
+    fix_owner(&*platform);
+    ret
+}
+
+/// sudo时安装器以root身份新建的文件要交给调用sudo的用户，否则QQ无法读取
+fn fix_owner(platform: &dyn Platform) {
+    let mut paths = vec![];
+    if let Ok(userdir) = platform.user_dir() {
+        paths.push(userdir.join(LITELOADER_DIR_NAME));
+        paths.push(userdir.join("LiteLoaderQQNT-main.zip"));
+        paths.push(userdir.join(backup::BACKUP_DIR_NAME));
+    }
+    if let Ok(cache_dir) = platform.cache_dir() {
+        paths.push(cache_dir);
+    }
+    for path in paths {
+        if let Err(err) = platform.fix_owner(&path) {
+            log::warn!("无法修改{path:?}的所有者: {err}");
+        }
+    }
+}
+
+fn run_command(platform: &dyn Platform, rt_ptr: Arc<tokio::runtime::Runtime>, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    match opts.command {
+        Command::Install | Command::Update => {
+            if std::env::var("LITELOADERQQNT_PROFILE").is_ok() {
+                return Err("检测到您的环境变量中存在LITELOADERQQNT_PROFILE，你可能已经手动安装过LiteLoaderQQNT，程序终止！".into());
+            }
+            let source: Box<dyn Source> = match &opts.bundle {
+                Some(bundle) => Box::new(BundleSource::open(bundle, get_pins(opts)?)?),
+                None => Box::new(NetworkSource::new(rt_ptr.clone(), get_network_options(platform, opts)?, get_pins(opts)?)?),
+            };
+            install(platform, &*source, &get_checksums(opts)?, &get_compat_manifest(rt_ptr, &read_cfg()?, opts)?, opts)
+        }
+        Command::Uninstall => uninstall(platform, opts),
+        Command::Restore => restore(platform, opts),
+        Command::Status => status::status(platform, opts),
+        Command::Cache => cache(platform, opts),
+        Command::Doctor => status::doctor(platform, rt_ptr, opts),
+        Command::Bundle => {
+            let output = get_apath(opts.output.as_deref().unwrap_or(Path::new("llob_bundle.zip")));
+            let source = NetworkSource::new(rt_ptr, get_network_options(platform, opts)?, get_pins(opts)?)?;
+            let manifest = bundle::build(&source, &get_checksums(opts)?, &output)?;
+            Ok(serde_json::json!({
+                "output": output,
+                "manifest": manifest,
+            }))
+        }
+        Command::Help => Ok(serde_json::json!({})),
     }
+}
 
-    let has_admin = is_admin().unwrap();
-    if has_admin {
-        log::info!("拥有管理员权限");
-    } else {
-        log::error!("没有管理员权限");
-        app_exit();
+/// 找到的QQ安装
+pub struct QQInstall {
+    pub qq_path: PathBuf,
+    pub qq_version: String,
+    pub layout: QQLayout,
+    pub qq_inner_path: PathBuf,
+    pub package_json_path: PathBuf,
+    /// 加载LiteLoaderQQNT的脚本，旧版QQ中是安装器新建的app_launcher/llqqnt.js
+    pub index_js_path: PathBuf,
+}
+
+impl QQInstall {
+    fn new(platform: &dyn Platform, qq_path: PathBuf, qq_version: String, layout: QQLayout) -> Self {
+        let qq_inner_path = platform.qq_inner_path(&qq_path, &qq_version);
+        QQInstall {
+            package_json_path: qq_inner_path.join("package.json"),
+            index_js_path: qq_inner_path.join("app_launcher").join(layout.launcher_name()),
+            qq_path,
+            qq_version,
+            layout,
+            qq_inner_path,
+        }
+    }
+
+    /// 比 other 新，即QQ已经下载好、重启后才会切换过去的版本
+    pub fn is_newer_than(&self, other: &QQInstall) -> bool {
+        match (QQVersion::parse(&self.qq_version), QQVersion::parse(&other.qq_version)) {
+            (Ok(version), Ok(other)) => version > other,
+            _ => false,
+        }
     }
 
+    /// index.js 中是否已经注入了LiteLoaderQQNT
+    pub fn is_injected(&self) -> bool {
+        fs::read_to_string(&self.index_js_path)
+            .map(|index_js| index_js.contains(LITELOADER_DIR_NAME))
+            .unwrap_or(false)
+    }
+}
+
+fn locate_qq(platform: &dyn Platform, opts: &Options) -> Result<QQInstall, Box<dyn std::error::Error>> {
     log::info!("正在查询QQ安装位置...");
     let qq_path;
-    if let Ok(qq_path_t) = get_qq_path() {
+    if let Some(path) = &opts.qq_path {
+        let path = get_apath(path);
+        if path.is_file() {
+            qq_path = path.parent().ok_or("can't find qq path")?.to_path_buf();
+        } else if path.join(platform.qq_exe_name()).is_file() {
+            qq_path = path;
+        } else {
+            return Err(format!("在{path:?}中没有找到{}", platform.qq_exe_name()).into());
+        }
+        log::info!("从命令行参数获取到QQ.exe");
+    } else if let Ok(qq_path_t) = get_qq_path(platform) {
         qq_path = qq_path_t;
-        log::info!("QQ安装位置: {:?}", qq_path);
     } else {
-        log::error!("未找到QQ安装位置,请去安装QQ!：https://im.qq.com/pcqq/index.shtml");
-        app_exit();
+        return Err("未找到QQ安装位置,请去安装QQ!：https://im.qq.com/pcqq/index.shtml".into());
     }
+    log::info!("QQ安装位置: {:?}", qq_path);
 
-    let qq_version = match get_qq_version(&qq_path) {
+    let qq_version = match platform.qq_version(&qq_path) {
         Ok(ver) => ver,
         Err(err) => {
-            log::error!("获取QQ版本号失败,注意当前安装器支持的最低NTQQ版本是9.9.15-28060:{err:?}\r\n如果您使用之前的NTQQ版本，请使用旧版安装器!");
-            app_exit();
+            return Err(format!("获取QQ版本号失败:{err:?}").into());
         },
     };
+    log::info!("QQ版本号: {qq_version}");
+    let layout = platform.qq_layout(&qq_path);
+    if layout == QQLayout::Legacy {
+        log::info!("检测到9.9.15之前的QQ目录结构，将使用旧的注入方式");
+    }
 
-    let qq_inner_path = qq_path.join("versions").join(qq_version).join("resources").join("app");
-    let package_json_path = qq_inner_path.join("package.json");
-    let index_js_path = qq_inner_path.join("app_launcher").join("index.js");
+    Ok(QQInstall::new(platform, qq_path, qq_version, layout))
+}
 
+/// versions目录下除了正在使用的版本以外的其它版本，按版本号从旧到新排序
+pub fn other_versions(platform: &dyn Platform, qq: &QQInstall) -> Vec<QQInstall> {
+    let mut versions = platform.installed_versions(&qq.qq_path);
+    versions.retain(|version| *version != qq.qq_version);
+    versions.sort_by(|a, b| match (QQVersion::parse(a), QQVersion::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    });
+    versions
+        .into_iter()
+        .map(|version| QQInstall::new(platform, qq.qq_path.clone(), version, qq.layout))
+        .collect()
+}
 
-    match is_qq_run(&qq_path) {
-        Ok(is_run) => {
-            if !is_run {
-                // log::info!("QQ未运行");
-            } else {
-                log::error!("QQ正在运行，安装LLONEBOT需要确保QQ处于未运行状态，请先结束QQ");
-                app_exit();
+/// 根据 --versions 选出除了正在使用的版本以外还要修改的版本
+fn extra_versions(platform: &dyn Platform, qq: &QQInstall, opts: &Options) -> Result<Vec<QQInstall>, Box<dyn std::error::Error>> {
+    let others = other_versions(platform, qq);
+    let extra: Vec<QQInstall> = match opts.versions.as_deref() {
+        None | Some("current") => {
+            let pending: Vec<&str> = others.iter().filter(|other| other.is_newer_than(qq)).map(|other| other.qq_version.as_str()).collect();
+            if !pending.is_empty() {
+                log::warn!("QQ已经下载了新版本{}，重启后会切换过去，可以使用--versions pending一起修改", pending.join("、"));
             }
+            vec![]
         }
+        Some("pending") => others.into_iter().filter(|other| other.is_newer_than(qq)).collect(),
+        Some("all") => others,
+        Some(versions) => return Err(format!("未知的--versions: {versions}，只能是current、pending或all").into()),
+    };
+    for other in &extra {
+        log::info!("同时修改QQ版本{}", other.qq_version);
+    }
+    Ok(extra)
+}
+
+/// 检查要安装的版本是否支持这个QQ，不支持时拒绝安装，--ignore-compat 时只警告
+fn check_compat(compat: &CompatManifest, qq: &QQInstall, versions: &[(Component, &str)], opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let qq_version = match QQVersion::parse(&qq.qq_version) {
+        Ok(qq_version) => qq_version,
         Err(err) => {
-            log::error!("无法检查QQ是否正在运行:{err:?}");
-            app_exit();
+            log::warn!("{err}，跳过兼容性检查");
+            return Ok(());
         }
+    };
+    let problems: Vec<String> = versions
+        .iter()
+        .filter_map(|(component, version)| compat.check(*component, version, &qq_version, qq.layout))
+        .collect();
+    if problems.is_empty() && qq.layout == QQLayout::Legacy && compat.is_builtin() {
+        log::warn!("内置的兼容性清单没有旧版QQ的版本范围，请确认安装的LiteLoaderQQNT和LLOneBot支持QQ {qq_version}，可以用--pin选择版本");
+        return Ok(());
+    }
+    if problems.is_empty() {
+        log::info!("兼容性检查通过");
+        return Ok(());
+    }
+    if opts.ignore_compat {
+        for problem in &problems {
+            log::warn!("{problem}");
+        }
+        log::warn!("已使用--ignore-compat跳过兼容性检查，安装后QQ可能无法正常运行");
+        return Ok(());
+    }
+    let hint = match qq.layout {
+        QQLayout::Legacy => "请使用--pin选择兼容性清单中支持这个旧版QQ的LiteLoaderQQNT和LLOneBot版本",
+        QQLayout::Current => "请更新QQ或使用--pin选择兼容的版本",
+    };
+    Err(format!("{}\r\n{hint}，确定要安装请使用--ignore-compat", problems.join("\r\n")).into())
+}
+
+/// 检查是否可以修改QQ
+fn check_can_modify(platform: &dyn Platform, qq: &QQInstall) -> Result<(), Box<dyn std::error::Error>> {
+    if platform.has_privilege(&qq.qq_inner_path)? {
+        log::info!("拥有修改QQ的权限");
+    } else {
+        return Err("没有修改QQ的权限，请以管理员身份运行(linux下请使用sudo)".into());
     }
-    let is_win32 = iswin32(&qq_path.join("QQ.exe"))?;
 
-    log::info!("正在获取github下载代理...");
-    let git_proxy = rt_ptr.block_on(async {
-        if let Some(proxy_t) = github_proxy().await {
-            if proxy_t == "https://github.com" {
-                log::info!("无需使用代理即可连接github");
-            } else {
-                log::info!("使用代理: {:?}", proxy_t);
+    match platform.is_qq_run(&qq.qq_path) {
+        Ok(is_run) => {
+            if is_run {
+                return Err("QQ正在运行，修改QQ需要确保QQ处于未运行状态，请先结束QQ".into());
             }
-            return proxy_t;
-        } else {
-            log::error!("无法获取github代理");
-            app_exit();
         }
-    });
+        Err(err) => {
+            return Err(format!("无法检查QQ是否正在运行:{err:?}").into());
+        }
+    }
+    Ok(())
+}
 
-    log::info!("正在获取最新QQNTFileVerifyPatch版本号...");
-    let url = "https://api.github.com/repos/LiteLoaderQQNT/QQNTFileVerifyPatch/releases/latest";
-    let bin = match http_post(rt_ptr.clone(), url, Some("Mozilla/5.0 (Windows NT 6.1; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/89.0.4389.72 Safari/537.36")) {
-        Ok(bin) => bin,
-        Err(_) => {
-            log::warn!("无法访问GitHub，尝试使用备用URL");
-            let backup_url = "https://api.hydroroll.team/api/version?repo=LiteLoaderQQNT/QQNTFileVerifyPatch&type=github-releases-latest";
-            match http_post(rt_ptr.clone(), backup_url, Some("Mozilla/5.0 (Windows NT 6.1; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/89.0.4389.72 Safari/537.36")) {
-                Ok(bin) => bin,
-                Err(_) => {
-                    log::error!("无法获取最新QQNTFileVerifyPatch版本号");
-                    app_exit();
-                }
-            }
+/// 需要用户确认的操作，使用 --yes 时直接返回true
+fn confirm(opts: &Options, prompt: &str) -> bool {
+    if opts.yes {
+        return true;
+    }
+    log::warn!("{prompt} [y/N]");
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line).is_err() {
+        return false;
+    }
+    matches!(line.trim(), "y" | "Y" | "yes")
+}
+
+/// 删除 LiteLoaderQQNT-main，keep_data 为true时保留其中的data目录
+fn remove_liteloader_dir(liteloader_path: &Path, keep_data: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if keep_data {
+        backup::clear_dir(liteloader_path, &["data"])
+    } else {
+        fs::remove_dir_all(liteloader_path)?;
+        Ok(())
+    }
+}
+
+fn uninstall(platform: &dyn Platform, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    // QQ已经被卸载时，也要能清理用户目录
+    let qq = match locate_qq(platform, opts) {
+        Ok(qq) => {
+            check_can_modify(platform, &qq)?;
+            Some(qq)
+        }
+        Err(err) => {
+            log::warn!("{err}，跳过还原QQ");
+            None
         }
     };
-    let version_json: serde_json::Value = serde_json::from_slice(&bin)?;
-    let tag_name = version_json["tag_name"]
-        .as_str()
-        .ok_or("Failed to get tag_name")?;
-    log::info!("最新QQNTFileVerifyPatch版本号:{tag_name}");
+    let userdir = platform.user_dir()?;
+    let liteloader_path = userdir.join(LITELOADER_DIR_NAME);
 
-    log::info!("正在下载修补文件...");
-    let patch_url;
-    if is_win32 {
-        patch_url = format!("{git_proxy}/LiteLoaderQQNT/QQNTFileVerifyPatch/releases/download/{tag_name}/dbghelp_x86.dll");
+    let prompt = if opts.keep_data {
+        format!("即将撤销安装器对QQ所做的修改，并删除{liteloader_path:?}(保留data目录)，是否继续?")
     } else {
-        patch_url = format!("{git_proxy}/LiteLoaderQQNT/QQNTFileVerifyPatch/releases/download/{tag_name}/dbghelp_x64.dll");
+        format!("即将撤销安装器对QQ所做的修改，并删除{liteloader_path:?}(包括插件的配置)，是否继续?")
+    };
+    if !confirm(opts, &prompt) {
+        return Err("已取消".into());
     }
-    let bin = match http_post(rt_ptr.clone(), &patch_url, None) {
-        Ok(bin) => bin,
-        Err(_) => {
-            log::error!("修补文件下载失败");
-            app_exit();
+
+    let backup_root = userdir.join(backup::BACKUP_DIR_NAME);
+    let mut reverted = vec![];
+    if let Some(qq) = &qq {
+        let patch_path = qq.qq_path.join(platform::PATCH_FILE_NAME);
+        if patch_path.is_file() {
+            log::info!("正在删除修补文件...");
+            fs::remove_file(&patch_path)?;
+            reverted.push(patch_path);
         }
-    };
-    log::info!("修补文件下载完成");
+        // 所有版本都要还原，包括之前用 --versions 修改的版本
+        for target in std::iter::once(qq).chain(&other_versions(platform, qq)) {
+            if target.is_injected() {
+                log::info!("正在还原{}的index.js...", target.qq_version);
+                restore_index_js(&target.index_js_path, target.layout, &backup_root)?;
+                reverted.push(target.index_js_path.clone());
+            }
+            // 有的QQ原来的main就和注入后的一样，这时不需要还原
+            if is_package_json_patched(&target.package_json_path, target.layout)? {
+                let original_main = original_main(&target.package_json_path, target.layout, &backup_root);
+                if original_main != target.layout.patched_main() {
+                    log::info!("正在还原{}的package.json...", target.qq_version);
+                    restore_package_json(&target.package_json_path, &original_main)?;
+                    reverted.push(target.package_json_path.clone());
+                }
+            }
+        }
+    }
 
-    log::info!("正在修补...");
-    fs::write(qq_path.join("dbghelp.dll"), bin)?;
-    log::info!("修补完成");
+    let zip_path = userdir.join("LiteLoaderQQNT-main.zip");
+    if zip_path.is_file() {
+        fs::remove_file(&zip_path)?;
+        reverted.push(zip_path);
+    }
+    if liteloader_path.is_dir() {
+        log::info!("正在删除{liteloader_path:?}...");
+        remove_liteloader_dir(&liteloader_path, opts.keep_data)?;
+        if opts.keep_data {
+            log::info!("已保留{:?}", liteloader_path.join("data"));
+        }
+        reverted.push(liteloader_path);
+    }
+
+    // 备份中有QQ原来的文件，默认保留
+    if backup_root.is_dir() {
+        if opts.purge_backups {
+            fs::remove_dir_all(&backup_root)?;
+            reverted.push(backup_root);
+        } else {
+            log::info!("备份保留在{backup_root:?}，使用--purge-backups可以一起删除");
+        }
+    }
+
+    log::info!("卸载完成");
+    Ok(serde_json::json!({
+        "qq_path": qq.as_ref().map(|qq| &qq.qq_path),
+        "qq_version": qq.as_ref().map(|qq| &qq.qq_version),
+        "reverted": reverted,
+        "keep_data": opts.keep_data,
+        "purge_backups": opts.purge_backups,
+    }))
+}
 
-    log::info!("正在下载LiteLoader项目...");
-    let patch_url = format!("{git_proxy}/LiteLoaderQQNT/LiteLoaderQQNT/archive/master.zip");
-    let bin = match http_post(rt_ptr.clone(), &patch_url, None) {
-        Ok(bin) => bin,
-        Err(_) => {
-            log::error!("LiteLoader项目下载失败");
-            app_exit();
+fn restore(platform: &dyn Platform, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let backup_root = platform.user_dir()?.join(backup::BACKUP_DIR_NAME);
+    let backups = Backup::list(&backup_root)?;
+
+    let Some(name) = &opts.snapshot else {
+        if backups.is_empty() {
+            log::info!("没有任何备份");
+        }
+        for backup in &backups {
+            log::info!("备份: {} 创建于 {}", backup.name, backup.manifest()["created"].as_str().unwrap_or_default());
         }
+        log::info!("使用 restore <备份名> 还原指定的备份，使用 restore latest 还原最新的备份");
+        return Ok(serde_json::json!({
+            "backups": backups.iter().map(|backup| serde_json::json!({
+                "name": backup.name,
+                "manifest": backup.manifest(),
+            })).collect::<Vec<_>>(),
+        }));
     };
-    log::info!("下载完成");
 
-    log::info!("正在解压...");
-    let userdir = PathBuf::from_str(&std::env::var("USERPROFILE")?)?;
-    let zip_path = userdir.join("LiteLoaderQQNT-main.zip");
-    fs::write(&zip_path, bin)?;
-    extrat(
-        &zip_path,
-        &zip_path
-            .parent()
-            .ok_or("can't get parent")?
-            .join("LiteLoaderQQNT-main"),
-        true,
-    )?;
-    log::info!("解压完成");
-    fix_index_js(&index_js_path,&userdir)?;
-    fix_package_json(&package_json_path)?;
-    log::info!("LiteLoaderQQNT安装完成");
-
-    log::info!("正在获取最新LLOB版本号...");
-    let url = "https://api.github.com/repos/LLOneBot/LLOneBot/releases/latest";
-    let bin = match http_post(rt_ptr.clone(), url, Some("Mozilla/5.0 (Windows NT 6.1; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/89.0.4389.72 Safari/537.36")) {
-        Ok(bin) => bin,
-        Err(_) => {
-            log::warn!("无法访问GitHub，尝试使用备用URL");
-            let backup_url = "https://api.hydroroll.team/api/version?repo=LLOneBot/LLOneBot&type=github-releases-latest";
-            match http_post(rt_ptr.clone(), backup_url, Some("Mozilla/5.0 (Windows NT 6.1; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/89.0.4389.72 Safari/537.36")) {
-                Ok(bin) => bin,
-                Err(_) => {
-                    log::error!("无法获取最新LLOB版本号");
-                    app_exit();
-                }
+    let backup = if name == "latest" {
+        backups.into_iter().last().ok_or("没有任何备份")?
+    } else {
+        Backup::open(&backup_root, name)?
+    };
+
+    if let (Some(qq_path), Some(qq_inner_path)) = (backup.qq_path(), backup.qq_inner_path()) {
+        if !platform.has_privilege(&qq_inner_path)? {
+            return Err("没有修改QQ的权限，请以管理员身份运行(linux下请使用sudo)".into());
+        }
+        if platform.is_qq_run(&qq_path)? {
+            return Err("QQ正在运行，修改QQ需要确保QQ处于未运行状态，请先结束QQ".into());
+        }
+    }
+    if !confirm(opts, &format!("即将还原备份{}，是否继续?", backup.name)) {
+        return Err("已取消".into());
+    }
+    backup.restore()?;
+    log::info!("已还原备份{}", backup.name);
+    Ok(serde_json::json!({
+        "backup": backup.name,
+    }))
+}
+
+/// 查看或清空下载缓存
+fn cache(platform: &dyn Platform, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let cache = Cache::new(&platform.cache_dir()?);
+    match opts.cache_action.as_deref() {
+        None | Some("list") => {
+            let files = cache.list()?;
+            if files.is_empty() {
+                log::info!("缓存为空");
             }
+            for file in &files {
+                log::info!(
+                    "{} {} {} ({}字节)",
+                    file["component"].as_str().unwrap_or_default(),
+                    file["version"].as_str().unwrap_or_default(),
+                    file["file"].as_str().unwrap_or_default(),
+                    file["size"]
+                );
+            }
+            log::info!("缓存目录: {:?}，使用 cache clean 清空缓存", cache.dir());
+            Ok(serde_json::json!({
+                "dir": cache.dir(),
+                "files": files,
+            }))
+        }
+        Some("clean") => {
+            let (count, size) = cache.clean()?;
+            log::info!("已删除{count}个缓存的文件，共{size}字节");
+            Ok(serde_json::json!({
+                "dir": cache.dir(),
+                "removed": count,
+                "size": size,
+            }))
         }
+        Some(action) => Err(format!("未知的cache操作: {action}，只能是list或clean").into()),
+    }
+}
+
+fn install(platform: &dyn Platform, source: &dyn Source, checksums: &Checksums, compat: &CompatManifest, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let qq = locate_qq(platform, opts)?;
+    // QQ自动更新后新版本还没有注入，只要有一个版本注入过就可以更新
+    let injected = qq.is_injected() || other_versions(platform, &qq).iter().any(|other| other.is_injected());
+    if opts.command == Command::Update && !injected {
+        return Err("尚未安装LiteLoaderQQNT，请使用install命令".into());
+    }
+    check_can_modify(platform, &qq)?;
+    let qq_path = &qq.qq_path;
+    let userdir = platform.user_dir()?;
+
+    let patch_artifact_name = platform.patch_artifact_name(qq_path)?;
+
+    // 先把所有文件都准备好，失败时不会对QQ做任何修改
+    let patch_bin = match patch_artifact_name {
+        Some(patch_artifact_name) => Some(source::fetch_verified(source, Component::VerifyPatch, patch_artifact_name, checksums)?.data),
+        None => None,
     };
-    let version_json: serde_json::Value = serde_json::from_slice(&bin)?;
-    let tag_name = version_json["tag_name"]
-        .as_str()
-        .ok_or("Failed to get tag_name")?;
-    log::info!("最新LLOB版本号:{tag_name}");
+    let liteloader = source::fetch_verified(source, Component::LiteLoader, source::LITELOADER_FILE_NAME, checksums)?;
+    let llonebot = source::fetch_verified(source, Component::LLOneBot, source::LLONEBOT_FILE_NAME, checksums)?;
+    let extra = extra_versions(platform, &qq, opts)?;
+    let targets: Vec<&QQInstall> = std::iter::once(&qq).chain(&extra).collect();
+    for target in &targets {
+        check_compat(compat, target, &[(Component::LiteLoader, &liteloader.version), (Component::LLOneBot, &llonebot.version)], opts)?;
+    }
+    let liteloader_bin = liteloader.data;
+    let tag_name = llonebot.version;
+    let llonebot_bin = llonebot.data;
+    if let Some(bin) = &patch_bin {
+        platform::check_patch_dll(bin, &qq_path.join(platform.qq_exe_name()))?;
+    }
+
+    // 再按顺序修改文件，任何一步失败都会回滚
+    std::fs::create_dir_all(&userdir)?;
+    let backup_root = userdir.join(backup::BACKUP_DIR_NAME);
+    let mut backup = Backup::create(&backup_root, qq_path, &qq.qq_inner_path)?;
+    log::info!("修改前的文件将备份到: {}", backup.name);
 
-    log::info!("正在下载LLOB项目...");
-    let patch_url = format!("{git_proxy}/LLOneBot/LLOneBot/releases/download/{tag_name}/LLOneBot.zip");
-    let bin = match http_post(rt_ptr.clone(), &patch_url, None) {
-        Ok(bin) => bin,
-        Err(_) => {
-            log::error!("LLOB项目下载失败");
-            app_exit();
+    let liteloader_path = userdir.join(LITELOADER_DIR_NAME);
+    let liteloader_zip_path = userdir.join("LiteLoaderQQNT-main.zip");
+    let injection = platform.injection();
+    let mut steps = vec![];
+    if let Some(bin) = patch_bin {
+        let patch_path = qq_path.join(platform::PATCH_FILE_NAME);
+        steps.push(Step::new("修补QQ", vec![(patch_path.clone(), &[])], move || {
+            fs::write(patch_path, bin)?;
+            Ok(())
+        }));
+    }
+    // data目录中是插件的配置，不需要备份也不会被覆盖
+    steps.push(Step::new("解压LiteLoaderQQNT", vec![(liteloader_path.clone(), &["data"]), (liteloader_zip_path.clone(), &[])], || {
+        fs::write(&liteloader_zip_path, liteloader_bin)?;
+        extrat(&liteloader_zip_path, &liteloader_path, true)
+    }));
+    let launcher_files = targets
+        .iter()
+        .flat_map(|target| [(target.index_js_path.clone(), &[] as &[&str]), (target.package_json_path.clone(), &[])])
+        .collect();
+    steps.push(Step::new("修改index.js和package.json", launcher_files, || {
+        for target in &targets {
+            match (target.layout, injection) {
+                (QQLayout::Current, Injection::Prepend) => prepend_index_js(&target.index_js_path,&userdir,&backup_root)?,
+                (QQLayout::Current, Injection::Launcher) => {
+                    fix_index_js(&target.index_js_path,&userdir)?;
+                    fix_package_json(&target.package_json_path, target.layout)?;
+                }
+                (QQLayout::Legacy, _) => {
+                    fix_legacy_launcher(&target.index_js_path,&userdir)?;
+                    fix_package_json(&target.package_json_path, target.layout)?;
+                }
+            }
         }
-    };
-    log::info!("下载完成");
-
-    log::info!("正在安装LLOnebOT...");
-    let zip_path = userdir
-        .join("LiteLoaderQQNT-main")
-        .join("plugins")
-        .join(format!("LLOneBot{tag_name}.zip"));
-    std::fs::create_dir_all(zip_path.parent().ok_or("can't get parent")?)?;
-    // 有时候没这个目录会报错
-    std::fs::create_dir_all(userdir.join("LiteLoaderQQNT-main").join("data"))?;
-    fs::write(&zip_path, bin)?;
-    extrat(
-        &zip_path,
-        &zip_path
-            .parent()
-            .ok_or("can't get parent")?
-            .join("LLOneBot"),
-        false,
-    )?;
-    log::info!("安装完成");
+        Ok(())
+    }));
+    steps.push(Step::new("安装LLOneBot", vec![], || {
+        let zip_path = liteloader_path
+            .join("plugins")
+            .join(format!("LLOneBot{tag_name}.zip"));
+        std::fs::create_dir_all(zip_path.parent().ok_or("can't get parent")?)?;
+        // 有时候没这个目录会报错
+        std::fs::create_dir_all(liteloader_path.join("data"))?;
+        fs::write(&zip_path, llonebot_bin)?;
+        extrat(
+            &zip_path,
+            &zip_path
+                .parent()
+                .ok_or("can't get parent")?
+                .join("LLOneBot"),
+            false,
+        )
+    }));
+    transaction::run(&mut backup, steps)?;
+    match Backup::prune(&backup_root, get_keep_backups()?) {
+        Ok(removed) if !removed.is_empty() => log::info!("已删除旧的备份: {}", removed.join("、")),
+        Ok(_) => {}
+        Err(err) => log::warn!("无法删除旧的备份: {err}"),
+    }
 
     log::info!("安装成功！！！！！！！！！享受快乐时光吧");
 
-    Ok(())
+    Ok(serde_json::json!({
+        "qq_path": qq.qq_path,
+        "qq_version": qq.qq_version,
+        "layout": qq.layout.name(),
+        "patched_versions": targets.iter().map(|target| &target.qq_version).collect::<Vec<_>>(),
+        "llonebot_version": tag_name,
+        "backup": backup.name,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use platform::fake::FakePlatform;
+    use source::{sha256_hex, Artifact};
+
+    /// 假QQ中原来的index.js和main，和内置的ORIGINAL_INDEX_JS、original_main不同，用来确认卸载时从备份还原
+    const QQ_INDEX_JS: &str = "require('../major.node').load('internal_index', module);\n";
+    const QQ_MAIN: &str = "./launcher/index.js";
+
+    /// 每个测试一个临时目录，开始前先清空
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("llob_install_test_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn zip_bytes(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, content) in files {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn liteloader_zip() -> Vec<u8> {
+        zip_bytes(&[("LiteLoaderQQNT-main/package.json", r#"{"version": "1.2.3"}"#)])
+    }
+
+    fn llonebot_zip() -> Vec<u8> {
+        zip_bytes(&[("manifest.json", r#"{"version": "4.0.0"}"#)])
+    }
+
+    /// 不访问网络的来源
+    struct FakeSource;
+
+    impl Source for FakeSource {
+        fn fetch(&self, component: Component, _file_name: &str) -> Result<Artifact, Box<dyn std::error::Error>> {
+            let (version, data) = match component {
+                Component::LiteLoader => ("1.2.3", liteloader_zip()),
+                Component::LLOneBot => ("v4.0.0", llonebot_zip()),
+                Component::VerifyPatch => return Err("假平台不需要修补文件".into()),
+            };
+            Ok(Artifact {
+                version: version.to_owned(),
+                sha256: Some(sha256_hex(&data)),
+                data,
+            })
+        }
+    }
+
+    /// LLOneBot不是有效zip的源，安装到最后一步会失败
+    struct BrokenLLOneBotSource;
+
+    impl Source for BrokenLLOneBotSource {
+        fn fetch(&self, component: Component, file_name: &str) -> Result<Artifact, Box<dyn std::error::Error>> {
+            if component != Component::LLOneBot {
+                return FakeSource.fetch(component, file_name);
+            }
+            let data = b"not a zip".to_vec();
+            Ok(Artifact {
+                version: "v4.0.0".to_owned(),
+                sha256: Some(sha256_hex(&data)),
+                data,
+            })
+        }
+    }
+
+    /// 不需要修补文件的假平台
+    fn fake_platform(root: &Path) -> FakePlatform {
+        let mut platform = FakePlatform::new(root);
+        platform.patch_artifact_name = None;
+        platform
+    }
+
+    /// 在假平台上放一个没有安装过的QQ
+    fn fake_qq(platform: &FakePlatform) -> QQInstall {
+        let qq_path = platform.qq_path.clone().unwrap();
+        fs::create_dir_all(&qq_path).unwrap();
+        fs::write(qq_path.join(platform.qq_exe_name()), b"MZ").unwrap();
+        let qq = QQInstall::new(platform, qq_path, platform.qq_version.clone(), platform.layout);
+        fs::create_dir_all(qq.index_js_path.parent().unwrap()).unwrap();
+        fs::write(&qq.index_js_path, QQ_INDEX_JS).unwrap();
+        fs::write(&qq.package_json_path, serde_json::json!({"main": QQ_MAIN, "version": qq.qq_version}).to_string()).unwrap();
+        qq
+    }
+
+    fn opts(args: &[&str], qq: &QQInstall) -> Options {
+        let qq_path = qq.qq_path.to_string_lossy().into_owned();
+        let args = args.iter().map(|arg| arg.to_string()).chain(["-y".to_owned(), "--qq-path".to_owned(), qq_path]);
+        cli::parse_args(args).unwrap()
+    }
+
+    #[test]
+    fn install_status_and_uninstall() {
+        let root = temp_root("install");
+        let platform = fake_platform(&root);
+        let qq = fake_qq(&platform);
+        let liteloader_path = platform.user_dir.join(LITELOADER_DIR_NAME);
+        let backup_root = platform.user_dir.join(backup::BACKUP_DIR_NAME);
+
+        let report = install(&platform, &FakeSource, &Checksums::default(), &CompatManifest::builtin(), &opts(&["install"], &qq)).unwrap();
+        assert_eq!(report["llonebot_version"], "v4.0.0");
+        assert!(qq.is_injected());
+        assert!(is_package_json_patched(&qq.package_json_path, qq.layout).unwrap());
+        assert!(liteloader_path.join("package.json").is_file());
+        assert!(liteloader_path.join("plugins").join("LLOneBot").join("manifest.json").is_file());
+        assert_eq!(Backup::list(&backup_root).unwrap().len(), 1);
+
+        let report = status::status(&platform, &opts(&["status"], &qq)).unwrap();
+        assert_eq!(report["injected"], true);
+        assert_eq!(report["liteloader_version"], "1.2.3");
+        assert_eq!(report["llonebot_version"], "4.0.0");
+
+        uninstall(&platform, &opts(&["uninstall"], &qq)).unwrap();
+        assert_eq!(fs::read_to_string(&qq.index_js_path).unwrap(), QQ_INDEX_JS);
+        let package_json: serde_json::Value = serde_json::from_slice(&fs::read(&qq.package_json_path).unwrap()).unwrap();
+        assert_eq!(package_json["main"], QQ_MAIN);
+        assert_eq!(package_json["version"], qq.qq_version);
+        assert!(!liteloader_path.exists());
+        // 备份默认保留
+        assert_eq!(Backup::list(&backup_root).unwrap().len(), 1);
+
+        let report = status::status(&platform, &opts(&["status"], &qq)).unwrap();
+        assert_eq!(report["injected"], false);
+        assert!(report["liteloader_version"].is_null());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prepend_injection_leaves_package_json_alone() {
+        let root = temp_root("prepend");
+        let mut platform = fake_platform(&root);
+        platform.injection = Injection::Prepend;
+        let qq = fake_qq(&platform);
+        let package_json = fs::read(&qq.package_json_path).unwrap();
+        let injected = liteloader_require(&platform.user_dir) + QQ_INDEX_JS;
+
+        // 再次安装时不会重复添加
+        for _ in 0..2 {
+            install(&platform, &FakeSource, &Checksums::default(), &CompatManifest::builtin(), &opts(&["install"], &qq)).unwrap();
+            assert_eq!(fs::read_to_string(&qq.index_js_path).unwrap(), injected);
+            assert_eq!(fs::read(&qq.package_json_path).unwrap(), package_json);
+        }
+        assert!(qq.is_injected());
+
+        uninstall(&platform, &opts(&["uninstall"], &qq)).unwrap();
+        assert_eq!(fs::read_to_string(&qq.index_js_path).unwrap(), QQ_INDEX_JS);
+        assert_eq!(fs::read(&qq.package_json_path).unwrap(), package_json);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn install_from_directory_bundle() {
+        let root = temp_root("bundle");
+        let platform = fake_platform(&root);
+        let qq = fake_qq(&platform);
+        let bundle_path = root.join("bundle");
+        fs::create_dir_all(&bundle_path).unwrap();
+        let mut components = serde_json::Map::new();
+        for (component, version, file_name, data) in [
+            (Component::LiteLoader, "1.2.3", source::LITELOADER_FILE_NAME, liteloader_zip()),
+            (Component::LLOneBot, "v4.0.0", source::LLONEBOT_FILE_NAME, llonebot_zip()),
+        ] {
+            fs::write(bundle_path.join(file_name), &data).unwrap();
+            components.insert(component.name().to_owned(), serde_json::json!({
+                "version": version,
+                "files": [{"name": file_name, "size": data.len(), "sha256": sha256_hex(&data)}],
+            }));
+        }
+        let manifest = serde_json::json!({"format": 1, "components": components});
+        fs::write(bundle_path.join(source::BUNDLE_MANIFEST_NAME), manifest.to_string()).unwrap();
+
+        let bundle_arg = bundle_path.to_string_lossy().into_owned();
+        let opts = opts(&["install", "--bundle", &bundle_arg], &qq);
+        let source = BundleSource::open(&bundle_path, Pins::new()).unwrap();
+        let report = install(&platform, &source, &Checksums::default(), &CompatManifest::builtin(), &opts).unwrap();
+        assert_eq!(report["llonebot_version"], "v4.0.0");
+        assert!(qq.is_injected());
+        assert!(platform.user_dir.join(LITELOADER_DIR_NAME).join("package.json").is_file());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn bundle_install_skips_remote_compat_manifest() {
+        let rt_ptr = Arc::new(tokio::runtime::Runtime::new().unwrap());
+        let cfg = serde_json::json!({"compat_manifest": "http://127.0.0.1:9/compat.json"});
+        let parse = |args: &[&str]| cli::parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        let err = get_compat_manifest(rt_ptr.clone(), &cfg, &parse(&["install", "--bundle", "llob_bundle.zip"])).err().unwrap();
+        assert!(err.to_string().contains("从离线包安装时不访问网络"));
+        let opts = parse(&["install", "--bundle", "llob_bundle.zip", "--ignore-compat"]);
+        assert!(get_compat_manifest(rt_ptr, &cfg, &opts).unwrap().is_builtin());
+    }
+
+    #[test]
+    fn unreadable_compat_manifest_fails_unless_ignored() {
+        let root = temp_root("compat");
+        let rt_ptr = Arc::new(tokio::runtime::Runtime::new().unwrap());
+        let manifest_path = root.join("compat.json");
+        let cfg = serde_json::json!({"compat_manifest": manifest_path});
+        let parse = |args: &[&str]| cli::parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+
+        assert!(get_compat_manifest(rt_ptr.clone(), &cfg, &parse(&["install"])).is_err());
+        fs::write(&manifest_path, "not json").unwrap();
+        assert!(get_compat_manifest(rt_ptr.clone(), &cfg, &parse(&["install"])).is_err());
+        assert!(get_compat_manifest(rt_ptr.clone(), &cfg, &parse(&["install", "--ignore-compat"])).unwrap().is_builtin());
+        fs::write(&manifest_path, r#"{"LLOneBot": [{"tag": "*", "max_build": 1}]}"#).unwrap();
+        assert!(!get_compat_manifest(rt_ptr, &cfg, &parse(&["install"])).unwrap().is_builtin());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn extrat_strips_only_a_single_top_level_folder() {
+        let root = temp_root("extrat");
+        for (name, files) in [
+            ("wrapped", &[("LiteLoaderQQNT-1.2.3/package.json", "{}"), ("LiteLoaderQQNT-1.2.3/src/main.js", "")][..]),
+            ("flat", &[("package.json", "{}"), ("src/main.js", "")][..]),
+        ] {
+            let zip_path = root.join(format!("{name}.zip"));
+            fs::write(&zip_path, zip_bytes(files)).unwrap();
+            extrat(&zip_path, &root.join(name), true).unwrap();
+            assert!(root.join(name).join("package.json").is_file());
+            assert!(root.join(name).join("src").join("main.js").is_file());
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unknown_versions_is_rejected_when_parsing_args() {
+        let parse = |args: &[&str]| cli::parse_args(args.iter().map(|arg| arg.to_string()));
+        assert!(parse(&["install", "--versions", "pending"]).is_ok());
+        let err = parse(&["install", "--versions", "newest"]).err().unwrap();
+        assert!(err.to_string().contains("newest"));
+    }
+
+    #[test]
+    fn failed_first_install_leaves_no_liteloader_dir() {
+        let root = temp_root("rollback");
+        let platform = fake_platform(&root);
+        let qq = fake_qq(&platform);
+
+        let err = install(&platform, &BrokenLLOneBotSource, &Checksums::default(), &CompatManifest::builtin(), &opts(&["install"], &qq)).unwrap_err();
+        assert!(err.to_string().contains("安装LLOneBot"));
+        assert!(!qq.is_injected());
+        assert!(!platform.user_dir.join(LITELOADER_DIR_NAME).exists());
+        assert_eq!(fs::read_to_string(&qq.index_js_path).unwrap(), QQ_INDEX_JS);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn install_refuses_while_qq_is_running() {
+        let root = temp_root("running");
+        let mut platform = fake_platform(&root);
+        platform.is_qq_run = true;
+        let qq = fake_qq(&platform);
+
+        let err = install(&platform, &FakeSource, &Checksums::default(), &CompatManifest::builtin(), &opts(&["install"], &qq)).unwrap_err();
+        assert!(err.to_string().contains("QQ正在运行"));
+        assert!(!qq.is_injected());
+        assert!(!platform.user_dir.join(LITELOADER_DIR_NAME).exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,243 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// 用来测速的文件，所有镜像都应该能下载到
+const PROBE_PATH: &str = "LiteLoaderQQNT/QQNTFileVerifyPatch/releases/download/DllHijack_1.0.8/dbghelp_x64.dll";
+/// 单个镜像测速的超时时间
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// 排序时按下载这么大的文件所需的时间比较，同时考虑延迟和速度
+const SCORE_SIZE: f64 = 5.0 * 1024.0 * 1024.0;
+/// 测速结果缓存在每个用户的缓存目录中，过期后重新测速
+pub const CACHE_NAME: &str = "mirrors.json";
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// github镜像，模板中的{path}会被替换为github上的路径，如 LiteLoaderQQNT/LLOneBot/releases/latest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    /// 替换 https://github.com/{path}
+    pub download: String,
+    /// 替换 https://api.github.com/{path}，为None时直接访问api.github.com
+    pub api: Option<String>,
+}
+
+/// 没有{path}时把模板当作前缀，模板必须是http或https的完整地址
+fn normalize_template(template: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let normalized = if template.contains("{path}") {
+        template.to_owned()
+    } else {
+        format!("{}/{{path}}", template.trim_end_matches('/'))
+    };
+    let url = reqwest::Url::parse(&normalized.replace("{path}", "path")).map_err(|err| format!("镜像地址错误: {template}: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("镜像地址必须以http://或https://开头: {template}").into());
+    }
+    Ok(normalized)
+}
+
+impl Mirror {
+    pub fn new(download: &str, api: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Mirror {
+            download: normalize_template(download)?,
+            api: api.map(normalize_template).transpose()?,
+        })
+    }
+
+    /// 配置文件中的镜像可以是字符串(只用于下载)，也可以是 {"download": ..., "api": ...}
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(download) = value.as_str() {
+            return Mirror::new(download, None);
+        }
+        let download = value["download"].as_str().ok_or(format!("镜像缺少download: {value}"))?;
+        Mirror::new(download, value["api"].as_str())
+    }
+
+    /// 命令行中的镜像，可以只写下载地址，也可以写成 download=<模板>,api=<模板>
+    pub fn from_arg(arg: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if !arg.starts_with("download=") && !arg.starts_with("api=") {
+            return Mirror::new(arg, None);
+        }
+        let (mut download, mut api) = (None, None);
+        for part in arg.split(',') {
+            match part.split_once('=') {
+                Some(("download", value)) if !value.is_empty() => download = Some(value),
+                Some(("api", value)) if !value.is_empty() => api = Some(value),
+                _ => return Err(format!("无法识别的镜像: {arg}，格式为 download=<地址>,api=<地址>").into()),
+            }
+        }
+        let download = download.ok_or(format!("镜像缺少download: {arg}"))?;
+        Mirror::new(download, api)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "download": self.download,
+            "api": self.api,
+        })
+    }
+
+    pub fn download_url(&self, path: &str) -> String {
+        self.download.replace("{path}", path)
+    }
+
+    pub fn api_url(&self, path: &str) -> String {
+        match &self.api {
+            Some(api) => api.replace("{path}", path),
+            None => format!("https://api.github.com/{path}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Mirror {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.download.trim_end_matches("/{path}"))
+    }
+}
+
+/// 没有配置镜像时使用的列表
+const DEFAULT_MIRRORS: &[&str] = &["https://kkgithub.com", "https://dgithub.xyz", "https://gh.jiasu.in/https://github.com", "https://github.com"];
+
+pub fn default_mirrors() -> Result<Vec<Mirror>, Box<dyn std::error::Error>> {
+    DEFAULT_MIRRORS.iter().map(|download| Mirror::new(download, None)).collect()
+}
+
+/// 一个镜像的测速结果
+#[derive(Debug, Clone)]
+pub struct Ranked {
+    pub mirror: Mirror,
+    pub latency: Duration,
+    /// 字节每秒
+    pub throughput: f64,
+}
+
+impl Ranked {
+    fn score(&self) -> f64 {
+        self.latency.as_secs_f64() + SCORE_SIZE / self.throughput.max(1.0)
+    }
+}
+
+async fn measure(mirror: Mirror) -> Result<Ranked, Box<dyn std::error::Error + Send + Sync>> {
+    let client = crate::net::client()?;
+    let start = Instant::now();
+    let ret = client.get(mirror.download_url(PROBE_PATH)).send().await?;
+    if ret.status() != reqwest::StatusCode::OK {
+        return Err(format!("状态码{}", ret.status()).into());
+    }
+    let latency = start.elapsed();
+    let bin = ret.bytes().await?;
+    if !bin.starts_with(b"MZ") {
+        return Err("返回的不是测速文件".into());
+    }
+    let elapsed = (start.elapsed() - latency).as_secs_f64().max(0.001);
+    Ok(Ranked {
+        mirror,
+        latency,
+        throughput: bin.len() as f64 / elapsed,
+    })
+}
+
+/// 同时对所有镜像测速，返回可用的镜像，最快的在前
+pub async fn measure_all(mirrors: &[Mirror]) -> Vec<Ranked> {
+    let handles: Vec<_> = mirrors
+        .iter()
+        .map(|mirror| tokio::spawn(tokio::time::timeout(PROBE_TIMEOUT, measure(mirror.clone()))))
+        .collect();
+    let mut ranked = vec![];
+    for (mirror, handle) in mirrors.iter().zip(handles) {
+        match handle.await {
+            Ok(Ok(Ok(result))) => {
+                log::info!("镜像{mirror}: 延迟{}ms，速度{:.1}KB/s", result.latency.as_millis(), result.throughput / 1024.0);
+                ranked.push(result);
+            }
+            Ok(Ok(Err(err))) => log::info!("镜像{mirror}不可用: {err}"),
+            _ => log::info!("镜像{mirror}超时"),
+        }
+    }
+    ranked.sort_by(|a, b| a.score().total_cmp(&b.score()));
+    ranked
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// 读取缓存的排序，镜像列表变化或者过期时返回None
+/// 只使用配置中有的镜像，缓存文件被改过也不会用到其它地址
+fn read_cache(cache_path: &Path, mirrors: &[Mirror]) -> Option<Vec<Mirror>> {
+    let json_str = std::fs::read_to_string(cache_path).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&json_str).ok()?;
+    if now_secs().saturating_sub(json["created"].as_u64()?) > CACHE_TTL.as_secs() {
+        return None;
+    }
+    let configured: Vec<serde_json::Value> = mirrors.iter().map(|mirror| mirror.to_json()).collect();
+    if json["configured"].as_array()? != &configured {
+        return None;
+    }
+    let ranked: Vec<Mirror> = json["ranked"]
+        .as_array()?
+        .iter()
+        .map(|mirror| Mirror::from_json(mirror).ok())
+        .collect::<Option<_>>()?;
+    Some(ranked.into_iter().filter(|mirror| mirrors.contains(mirror)).collect())
+}
+
+fn write_cache(cache_path: &Path, mirrors: &[Mirror], ranked: &[Ranked]) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::json!({
+        "created": now_secs(),
+        "configured": mirrors.iter().map(|mirror| mirror.to_json()).collect::<Vec<_>>(),
+        "ranked": ranked.iter().map(|ranked| {
+            let mut json = ranked.mirror.to_json();
+            json["latency_ms"] = (ranked.latency.as_millis() as u64).into();
+            json["throughput"] = ranked.throughput.into();
+            json
+        }).collect::<Vec<_>>(),
+    });
+    if let Some(parent) = cache_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(cache_path, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
+}
+
+/// 对镜像排序，优先使用cache_path中缓存的结果，refresh 为true时总是重新测速
+pub fn rank(
+    rt_ptr: &Arc<tokio::runtime::Runtime>,
+    mirrors: &[Mirror],
+    refresh: bool,
+    cache_path: &Path,
+) -> Result<Vec<Mirror>, Box<dyn std::error::Error>> {
+    if !refresh {
+        if let Some(ranked) = read_cache(cache_path, mirrors) {
+            if let Some(best) = ranked.first() {
+                log::info!("使用缓存的镜像测速结果，最快的镜像: {best}");
+                return Ok(ranked);
+            }
+        }
+    }
+    log::info!("正在对{}个github镜像测速...", mirrors.len());
+    let ranked = rt_ptr.block_on(measure_all(mirrors));
+    let best = ranked.first().ok_or("所有github镜像都不可用")?;
+    log::info!("最快的镜像: {}", best.mirror);
+    if let Err(err) = write_cache(cache_path, mirrors, &ranked) {
+        log::warn!("无法保存镜像测速结果: {err}");
+    }
+    Ok(ranked.into_iter().map(|ranked| ranked.mirror).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_must_be_absolute_http_urls() {
+        let mirror = Mirror::from_arg("download=https://kkgithub.com,api=https://kkgithub.com/api/{path}").unwrap();
+        assert_eq!(mirror.download_url("a/b"), "https://kkgithub.com/a/b");
+        assert_eq!(mirror.api_url("repos/a"), "https://kkgithub.com/api/repos/a");
+        assert!(Mirror::from_arg("download=https://kkgithub.com,api=kkgithub.com/api").is_err());
+        assert!(Mirror::from_json(&serde_json::json!("ftp://example.com")).is_err());
+        assert!(Mirror::from_json(&serde_json::json!({"download": "https://example.com", "api": "/api"})).is_err());
+        assert_eq!(default_mirrors().unwrap().len(), DEFAULT_MIRRORS.len());
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// 访问网络时使用的代理
+#[derive(Debug, Clone)]
+pub enum ProxySetting {
+    /// 使用环境变量(HTTP_PROXY/HTTPS_PROXY/ALL_PROXY)和系统代理
+    System,
+    /// 不使用任何代理
+    Direct,
+    /// 配置文件中指定的代理，支持http和socks5，可以带用户名和密码
+    Custom(reqwest::Proxy),
+}
+
+impl ProxySetting {
+    /// 解析配置文件中的proxy，不填时使用系统代理，"direct"表示直连
+    pub fn parse(proxy: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        match proxy {
+            None | Some("") | Some("system") => Ok(ProxySetting::System),
+            Some("direct") => Ok(ProxySetting::Direct),
+            Some(url) => {
+                let scheme = url.split_once("://").map(|(scheme, _)| scheme).unwrap_or_default();
+                if !matches!(scheme, "http" | "https" | "socks5" | "socks5h") {
+                    return Err(format!("不支持的代理: {url}，只支持http、https、socks5和socks5h").into());
+                }
+                let proxy = reqwest::Proxy::all(url).map_err(|err| format!("代理地址错误: {err}"))?;
+                Ok(ProxySetting::Custom(proxy))
+            }
+        }
+    }
+}
+
+/// 所有请求共用的网络设置
+#[derive(Debug, Clone)]
+pub struct NetSettings {
+    pub proxy: ProxySetting,
+    /// 额外信任的根证书，用于公司内部的https代理
+    pub ca_certs: Vec<reqwest::Certificate>,
+    /// 不校验证书，只能通过 --insecure 打开
+    pub insecure: bool,
+    /// 访问github api时使用的token，可以提高访问次数的上限
+    pub github_token: Option<String>,
+}
+
+static SETTINGS: OnceLock<NetSettings> = OnceLock::new();
+
+/// 读取pem格式的证书文件，一个文件中可以有多个证书
+pub fn load_ca_bundle(path: &Path) -> Result<Vec<reqwest::Certificate>, Box<dyn std::error::Error>> {
+    let pem = std::fs::read(path).map_err(|err| format!("无法读取证书文件{path:?}: {err}"))?;
+    let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|err| format!("证书文件{path:?}格式错误: {err}"))?;
+    if certs.is_empty() {
+        return Err(format!("证书文件{path:?}中没有证书").into());
+    }
+    log::info!("从{path:?}加载了{}个证书", certs.len());
+    Ok(certs)
+}
+
+/// 设置之后所有请求使用的网络设置，只有第一次设置有效
+pub fn configure(settings: NetSettings) {
+    match &settings.proxy {
+        ProxySetting::System => log::debug!("使用系统代理"),
+        ProxySetting::Direct => log::info!("不使用代理"),
+        ProxySetting::Custom(_) => log::info!("使用配置文件中的代理"),
+    }
+    if settings.github_token.is_some() {
+        log::info!("访问GitHub API时将使用token");
+    }
+    if settings.insecure {
+        log::warn!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
+        log::warn!("已使用--insecure关闭https证书校验！");
+        log::warn!("下载的文件(包括会被QQ加载的dll)可能被网络中的任何人替换，请只在信任的网络中使用");
+        log::warn!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
+    }
+    let _err = SETTINGS.set(settings);
+}
+
+/// 只有直接访问api.github.com时才返回token，不会把token发给镜像
+pub fn github_token_for(url: &str) -> Option<&'static str> {
+    let url = reqwest::Url::parse(url).ok()?;
+    if url.scheme() != "https" || url.host_str() != Some("api.github.com") {
+        return None;
+    }
+    SETTINGS.get()?.github_token.as_deref()
+}
+
+/// 创建http客户端，所有请求都应该通过这里创建客户端
+pub fn client() -> Result<reqwest::Client, reqwest::Error> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(settings) = SETTINGS.get() {
+        builder = match &settings.proxy {
+            ProxySetting::System => builder,
+            ProxySetting::Direct => builder.no_proxy(),
+            ProxySetting::Custom(proxy) => builder.proxy(proxy.clone()),
+        };
+        for cert in &settings.ca_certs {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        builder = builder.danger_accept_invalid_certs(settings.insecure);
+    }
+    builder.build()
+}
+
+/// 期望服务器返回的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    /// github api返回的json
+    Json,
+    /// 要下载的文件
+    Binary,
+}
+
+/// 把github返回的限流重置时间(unix时间戳)转换成本地时间
+fn format_reset_time(reset: &str) -> String {
+    let Ok(reset) = reset.parse::<i64>() else {
+        return reset.to_owned();
+    };
+    let utc_offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::from_hms(8, 0, 0).unwrap());
+    time::OffsetDateTime::from_unix_timestamp(reset)
+        .ok()
+        .and_then(|reset| {
+            let format = time::format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").ok()?;
+            reset.to_offset(utc_offset).format(&format).ok()
+        })
+        .unwrap_or_else(|| reset.to_string())
+}
+
+/// 检查状态码、github的限流和内容类型，出错时返回具体的原因
+pub async fn check_response(ret: reqwest::Response, expect: Expect) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let status = ret.status();
+    let headers = ret.headers();
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_owned());
+    if matches!(status.as_u16(), 403 | 429) {
+        if header("x-ratelimit-remaining").as_deref() == Some("0") {
+            let reset = header("x-ratelimit-reset").map(|reset| format_reset_time(&reset)).unwrap_or("未知".to_owned());
+            return Err(format!("GitHub API访问次数已达上限，将在{reset}重置").into());
+        }
+        if let Some(retry_after) = header("retry-after") {
+            return Err(format!("GitHub API访问过于频繁，请在{retry_after}秒后重试").into());
+        }
+    }
+    if let Some(remaining) = header("x-ratelimit-remaining") {
+        let limit = header("x-ratelimit-limit").unwrap_or("?".to_owned());
+        let reset = header("x-ratelimit-reset").map(|reset| format_reset_time(&reset)).unwrap_or("未知".to_owned());
+        log::debug!("GitHub API剩余访问次数: {remaining}/{limit}，将在{reset}重置");
+    }
+    let content_type = header("content-type").unwrap_or_default();
+    if !status.is_success() {
+        // github api出错时会在json中说明原因
+        let message = if content_type.starts_with("application/json") {
+            let body = ret.bytes().await.unwrap_or_default();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            body["message"].as_str().map(|message| format!(": {message}")).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        return Err(format!("服务器返回{status}{message}").into());
+    }
+    if content_type.starts_with("text/html") {
+        return Err(format!("服务器返回了网页而不是{}，可能是镜像出错或需要登录", expect.describe()).into());
+    }
+    Ok(ret)
+}
+
+impl Expect {
+    fn describe(&self) -> &'static str {
+        match self {
+            Expect::Json => "json",
+            Expect::Binary => "文件",
+        }
+    }
+}
+
+/// 有的服务器不返回content-type，再看一下内容是不是网页
+pub fn check_body(body: &[u8], expect: Expect) -> Result<(), Box<dyn std::error::Error>> {
+    let start = String::from_utf8_lossy(&body[..body.len().min(256)]).trim_start().to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        return Err(format!("服务器返回了网页而不是{}，可能是镜像出错或需要登录", expect.describe()).into());
+    }
+    Ok(())
+}
+
+/// 下载时每隔这么久输出一次进度
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 把字节数格式化成方便阅读的形式
+fn format_size(size: f64) -> String {
+    if size >= 1024.0 * 1024.0 {
+        format!("{:.1}MB", size / 1024.0 / 1024.0)
+    } else {
+        format!("{:.1}KB", size / 1024.0)
+    }
+}
+
+fn log_progress(downloaded: u64, total: Option<u64>, resumed: u64, start: Instant) {
+    let rate = (downloaded - resumed) as f64 / start.elapsed().as_secs_f64().max(0.001);
+    match total {
+        Some(total) if total > 0 => {
+            let eta = total.saturating_sub(downloaded) as f64 / rate.max(1.0);
+            log::info!(
+                "已下载{}/{}({}%)，速度{}/s，剩余约{:.0}秒",
+                format_size(downloaded as f64),
+                format_size(total as f64),
+                downloaded * 100 / total,
+                format_size(rate),
+                eta
+            );
+        }
+        _ => log::info!("已下载{}，速度{}/s", format_size(downloaded as f64), format_size(rate)),
+    }
+}
+
+/// 断点文件旁边记录服务器返回的ETag或Last-Modified，续传时用If-Range确认文件没有变化
+fn validator_path(part_path: &Path) -> PathBuf {
+    let mut name = part_path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// 读取断点对应的校验值，地址不同或没有记录时不能续传
+fn read_validator(part_path: &Path, url: &str) -> Option<String> {
+    let bin = fs::read(validator_path(part_path)).ok()?;
+    let json: serde_json::Value = serde_json::from_slice(&bin).ok()?;
+    if json["url"] != url {
+        return None;
+    }
+    json["validator"].as_str().map(|validator| validator.to_owned())
+}
+
+/// 强ETag优先，弱ETag不能用于If-Range，这时改用Last-Modified
+fn response_validator(ret: &reqwest::Response) -> Option<String> {
+    let header = |name| ret.headers().get(name).and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok());
+    header(reqwest::header::ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(reqwest::header::LAST_MODIFIED))
+        .map(|validator| validator.to_owned())
+}
+
+/// 删除断点文件和校验值，不存在时忽略
+fn remove_part(part_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    for path in [part_path.to_path_buf(), validator_path(part_path)] {
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+async fn download_async(url: &str, part_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let client = client()?;
+    // 只从普通文件继续，不跟随符号链接
+    let mut downloaded = match fs::symlink_metadata(part_path) {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => 0,
+    };
+    let validator = if downloaded > 0 { read_validator(part_path, url) } else { None };
+    let mut req = client.get(url);
+    match &validator {
+        Some(validator) => {
+            req = req
+                .header(reqwest::header::RANGE, format!("bytes={downloaded}-"))
+                .header(reqwest::header::IF_RANGE, validator.as_str());
+        }
+        None => downloaded = 0,
+    }
+    let mut ret = req.send().await?;
+    if ret.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // 已下载的部分和服务器上的文件对不上，从头下载
+        downloaded = 0;
+        ret = client.get(url).send().await?;
+    }
+    let mut ret = check_response(ret, Expect::Binary).await?;
+    let status = ret.status();
+    let mut file = if status == reqwest::StatusCode::PARTIAL_CONTENT && downloaded > 0 {
+        log::info!("从{}处继续下载", format_size(downloaded as f64));
+        OpenOptions::new().append(true).open(part_path)?
+    } else {
+        // 服务器不支持断点续传或文件已经变化，从头开始
+        downloaded = 0;
+        remove_part(part_path)?;
+        let file = OpenOptions::new().write(true).create_new(true).open(part_path)?;
+        if let Some(validator) = response_validator(&ret) {
+            let json = serde_json::json!({"url": url, "validator": validator});
+            fs::write(validator_path(part_path), json.to_string())?;
+        }
+        file
+    };
+    let total = ret.content_length().map(|len| len + downloaded);
+    let resumed = downloaded;
+    let start = Instant::now();
+    let mut last_report = start;
+    while let Some(chunk) = ret.chunk().await? {
+        file.write_all(&chunk)?;
+        downloaded += chunk.len() as u64;
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            log_progress(downloaded, total, resumed, start);
+            last_report = Instant::now();
+        }
+    }
+    file.flush()?;
+    if let Some(total) = total {
+        if downloaded < total {
+            return Err(format!("下载不完整，只下载了{downloaded}/{total}字节").into());
+        }
+    }
+    log_progress(downloaded, total, resumed, start);
+    Ok(())
+}
+
+/// 把url下载到part_path，part_path是同一个url留下的断点时用Range和If-Range继续
+/// 下载完成后返回文件内容并删除part_path，失败时保留已下载的部分
+pub fn download(rt_ptr: &Arc<tokio::runtime::Runtime>, url: &str, part_path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if let Some(parent) = part_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    rt_ptr.block_on(download_async(url, part_path))?;
+    let data = fs::read(part_path)?;
+    remove_part(part_path)?;
+    check_body(&data, Expect::Binary)?;
+    Ok(data)
+}
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+use super::{Injection, Platform, QQLayout};
+
+/// 不访问系统的假平台，用于在任意系统上跑通安装流程
+pub struct FakePlatform {
+    pub qq_path: Option<PathBuf>,
+    pub qq_version: String,
+    pub layout: QQLayout,
+    pub injection: Injection,
+    pub user_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub has_privilege: bool,
+    pub is_qq_run: bool,
+    pub patch_artifact_name: Option<&'static str>,
+}
+
+impl FakePlatform {
+    /// 一个QQ装在 root/QQ、用户目录在 root/home 的平台
+    pub fn new(root: &Path) -> Self {
+        FakePlatform {
+            qq_path: Some(root.join("QQ")),
+            qq_version: "9.9.15-28060".to_owned(),
+            layout: QQLayout::Current,
+            injection: Injection::Launcher,
+            user_dir: root.join("home"),
+            cache_dir: root.join("cache"),
+            has_privilege: true,
+            is_qq_run: false,
+            patch_artifact_name: Some("dbghelp_x64.dll"),
+        }
+    }
+}
+
+impl Platform for FakePlatform {
+    fn qq_exe_name(&self) -> &'static str {
+        "QQ.exe"
+    }
+
+    fn find_qq_path(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.qq_path.clone().ok_or_else(|| "can't find qq path".into())
+    }
+
+    fn qq_layout(&self, _qq_path: &Path) -> QQLayout {
+        self.layout
+    }
+
+    fn qq_version(&self, _qq_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(self.qq_version.clone())
+    }
+
+    fn qq_inner_path(&self, qq_path: &Path, qq_version: &str) -> PathBuf {
+        if self.layout == QQLayout::Legacy {
+            return qq_path.join("resources").join("app");
+        }
+        qq_path.join("versions").join(qq_version).join("resources").join("app")
+    }
+
+    fn has_privilege(&self, _qq_inner_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.has_privilege)
+    }
+
+    fn user_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(self.user_dir.clone())
+    }
+
+    fn cache_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(self.cache_dir.clone())
+    }
+
+    fn injection(&self) -> Injection {
+        self.injection
+    }
+
+    fn patch_artifact_name(&self, _qq_path: &Path) -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+        Ok(self.patch_artifact_name)
+    }
+
+    fn is_qq_run(&self, _qq_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.is_qq_run)
+    }
+}
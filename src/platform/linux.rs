@@ -0,0 +1,175 @@
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::{Injection, Platform};
+
+pub struct LinuxPlatform;
+
+fn has_write_access(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    Ok(unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } == 0)
+}
+
+/// 通过sudo运行的用户
+struct SudoUser {
+    uid: u32,
+    gid: u32,
+    home: PathBuf,
+}
+
+/// 以root身份通过sudo运行时，返回调用sudo的用户，LiteLoaderQQNT要装到这个用户的home中
+fn sudo_user() -> Result<Option<SudoUser>, Box<dyn std::error::Error>> {
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(None);
+    }
+    let uid = std::env::var("SUDO_UID").ok().and_then(|uid| uid.parse::<u32>().ok());
+    let name = std::env::var("SUDO_USER").ok();
+    if (uid.is_none() && name.is_none()) || uid == Some(0) || name.as_deref() == Some("root") {
+        return Ok(None);
+    }
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let ret = match (uid, &name) {
+        (Some(uid), _) => unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) },
+        (None, Some(name)) => {
+            let c_name = CString::new(name.as_bytes())?;
+            unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) }
+        }
+        (None, None) => unreachable!(),
+    };
+    if ret != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return Err(format!("无法获取sudo用户{}的home目录，请不要使用sudo，或者设置--qq-path后以QQ用户运行", name.unwrap_or_default()).into());
+    }
+    let home = PathBuf::from(OsStr::from_bytes(unsafe { CStr::from_ptr(passwd.pw_dir) }.to_bytes()));
+    Ok(Some(SudoUser {
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+        home,
+    }))
+}
+
+fn chown_all(path: &Path, uid: u32, gid: u32) -> Result<(), Box<dyn std::error::Error>> {
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+    if path.symlink_metadata()?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_all(&entry?.path(), uid, gid)?;
+        }
+    }
+    Ok(())
+}
+
+/// home目录，sudo时为调用sudo的用户的home
+fn home_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(user) = sudo_user()? {
+        return Ok(user.home);
+    }
+    Ok(PathBuf::from_str(&std::env::var("HOME")?)?)
+}
+
+impl Platform for LinuxPlatform {
+    fn qq_exe_name(&self) -> &'static str {
+        "qq"
+    }
+
+    fn find_qq_path(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        // deb/rpm 包的默认安装位置
+        let mut candidates = vec![
+            PathBuf::from("/opt/QQ"),
+            PathBuf::from("/usr/lib/QQ"),
+            PathBuf::from("/usr/share/QQ"),
+        ];
+        // AppImage 需要先用 --appimage-extract 解压，解压出来的目录叫 squashfs-root
+        if let Ok(current_dir) = std::env::current_dir() {
+            candidates.push(current_dir.join("squashfs-root"));
+        }
+        if let Some(current_path) = std::env::current_exe()?.parent() {
+            candidates.push(current_path.join("squashfs-root"));
+        }
+        for qq_path in candidates {
+            if qq_path.join(self.qq_exe_name()).is_file() && qq_path.join("resources").join("app").is_dir() {
+                log::info!("从默认安装位置获取到qq");
+                return Ok(qq_path);
+            }
+        }
+        Err("can't find qq in default location".into())
+    }
+
+    fn qq_version(&self, qq_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        // linux版没有versions目录，版本号写在resources/app/package.json里面
+        let package_json_path = qq_path.join("resources").join("app").join("package.json");
+        let package_str = std::fs::read_to_string(package_json_path)?;
+        let package_json: serde_json::Value = serde_json::from_str(&package_str)?;
+        let version = package_json["version"].as_str().ok_or("解析package.json失败")?;
+        Ok(version.to_owned())
+    }
+
+    fn installed_versions(&self, _qq_path: &Path) -> Vec<String> {
+        // linux版只有一个resources/app
+        vec![]
+    }
+
+    fn qq_inner_path(&self, qq_path: &Path, _qq_version: &str) -> PathBuf {
+        qq_path.join("resources").join("app")
+    }
+
+    fn has_privilege(&self, qq_inner_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        // linux下不一定需要root，只要能写QQ的resources/app目录就行
+        has_write_access(qq_inner_path)
+    }
+
+    fn user_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        // 优先使用XDG_DATA_HOME，否则使用 ~/.local/share
+        // sudo时XDG_DATA_HOME和HOME可能是root的，总是使用调用sudo的用户的home
+        if let (Ok(data_home), None) = (std::env::var("XDG_DATA_HOME"), sudo_user()?) {
+            let data_home = PathBuf::from(data_home);
+            if data_home.is_absolute() {
+                return Ok(data_home);
+            }
+        }
+        Ok(home_dir()?.join(".local").join("share"))
+    }
+
+    fn cache_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        // 优先使用XDG_CACHE_HOME，否则使用 ~/.cache
+        if let (Ok(cache_home), None) = (std::env::var("XDG_CACHE_HOME"), sudo_user()?) {
+            let cache_home = PathBuf::from(cache_home);
+            if cache_home.is_absolute() {
+                return Ok(cache_home.join("llob_install"));
+            }
+        }
+        Ok(home_dir()?.join(".cache").join("llob_install"))
+    }
+
+    fn fix_owner(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(user) = sudo_user()? else {
+            return Ok(());
+        };
+        // 以root身份新建的上级目录(如 ~/.local/share)也要交给用户
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.starts_with(&user.home) || ancestor == user.home {
+                break;
+            }
+            use std::os::unix::fs::MetadataExt;
+            if ancestor.symlink_metadata().map(|meta| meta.uid() == 0).unwrap_or(false) {
+                std::os::unix::fs::lchown(ancestor, Some(user.uid), Some(user.gid))?;
+            }
+        }
+        if path.symlink_metadata().is_ok() {
+            chown_all(path, user.uid, user.gid)?;
+        }
+        Ok(())
+    }
+
+    fn patch_artifact_name(&self, _qq_path: &Path) -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+        // linux版QQ没有文件校验，不需要修补
+        Ok(None)
+    }
+
+    fn injection(&self) -> Injection {
+        // package.json属于root，QQ以普通用户运行时不能修改它
+        Injection::Prepend
+    }
+}
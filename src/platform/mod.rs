@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+pub mod fake;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+/// QQ的目录结构，决定了注入LiteLoaderQQNT的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QQLayout {
+    /// 9.9.15及之后的结构，通过替换app_launcher/index.js中转到LiteLoaderQQNT
+    Current,
+    /// 9.9.15之前的windows版，resources/app直接在安装目录下，
+    /// 在app_launcher中新建一个脚本，并把package.json的main指向它
+    Legacy,
+}
+
+impl QQLayout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QQLayout::Current => "current",
+            QQLayout::Legacy => "legacy",
+        }
+    }
+
+    /// 安装前package.json中的main
+    pub fn original_main(&self) -> &'static str {
+        match self {
+            QQLayout::Current => "./application/app_launcher/index.js",
+            QQLayout::Legacy => "./app_launcher/index.js",
+        }
+    }
+
+    /// 安装后package.json中的main
+    pub fn patched_main(&self) -> &'static str {
+        match self {
+            QQLayout::Current => "./app_launcher/index.js",
+            QQLayout::Legacy => "./app_launcher/llqqnt.js",
+        }
+    }
+
+    /// app_launcher中用于加载LiteLoaderQQNT的脚本
+    pub fn launcher_name(&self) -> &'static str {
+        match self {
+            QQLayout::Current => "index.js",
+            QQLayout::Legacy => "llqqnt.js",
+        }
+    }
+}
+
+/// 在9.9.15之后的目录结构中注入LiteLoaderQQNT的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Injection {
+    /// 替换index.js，启动时临时改回package.json的main，用于绕过windows版的文件校验
+    Launcher,
+    /// 只在原来的index.js最前面加一行require，不修改package.json
+    Prepend,
+}
+
+/// 和操作系统相关的操作都放在这里，mymain 只通过这个trait和系统打交道
+pub trait Platform {
+    /// QQ主程序的文件名
+    fn qq_exe_name(&self) -> &'static str;
+
+    /// 在系统中查找QQ的安装目录(注册表、默认安装位置等)
+    fn find_qq_path(&self) -> Result<PathBuf, Box<dyn std::error::Error>>;
+
+    /// QQ的目录结构，只有windows上有旧的结构
+    fn qq_layout(&self, _qq_path: &Path) -> QQLayout {
+        QQLayout::Current
+    }
+
+    /// 读取QQ的版本号
+    fn qq_version(&self, qq_path: &Path) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// versions目录下安装的所有版本，QQ自动更新后这里会有多个版本
+    fn installed_versions(&self, qq_path: &Path) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(qq_path.join("versions")) else {
+            return vec![];
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("resources").join("app").join("package.json").is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// QQ的resources/app目录，package.json和app_launcher都在里面
+    fn qq_inner_path(&self, qq_path: &Path, qq_version: &str) -> PathBuf;
+
+    /// 是否有权限修改 qq_inner_path
+    fn has_privilege(&self, qq_inner_path: &Path) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// LiteLoaderQQNT-main 所在的目录
+    fn user_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>>;
+
+    /// 下载缓存所在的目录
+    fn cache_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>>;
+
+    /// 把安装器在用户目录中新建的文件交给用户，linux下通过sudo运行时需要
+    fn fix_owner(&self, _path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    /// 注入LiteLoaderQQNT的方式，没有文件校验的平台不需要修改package.json
+    fn injection(&self) -> Injection {
+        Injection::Launcher
+    }
+
+    /// QQNTFileVerifyPatch 中需要下载的文件名，返回None表示这个平台不需要修补
+    fn patch_artifact_name(&self, qq_path: &Path) -> Result<Option<&'static str>, Box<dyn std::error::Error>>;
+
+    /// 检查QQ是否正在运行
+    fn is_qq_run(&self, qq_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        let system = sysinfo::System::new_all();
+        // processes_by_name 按子串匹配，这里要求进程名完全相同，并检查所有同名进程
+        for process in system.processes().values().filter(|process| process.name() == self.qq_exe_name()) {
+            // 其它用户的进程读不到exe，用命令行中的路径代替
+            let process_exe_path = match process.exe() {
+                Some(exe) => exe.to_path_buf(),
+                None => match process.cmd().first() {
+                    Some(cmd) => PathBuf::from(cmd),
+                    None => {
+                        log::debug!("无法获取进程{}的路径", process.pid());
+                        continue;
+                    }
+                },
+            };
+            if process_exe_path.parent() == Some(qq_path) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// 修补文件在QQ目录下的文件名
+pub const PATCH_FILE_NAME: &str = "dbghelp.dll";
+
+/// 修补文件必须导出的函数，QQ崩溃时通过它写dump
+const PATCH_EXPORTS: &[&str] = &["MiniDumpWriteDump"];
+
+/// 写入修补文件前检查它是一个和QQ架构相同的dll
+pub fn check_patch_dll(dll_data: &[u8], qq_exe_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    use goblin::{pe::header::machine_to_str, Object};
+    let qq_exe_data = std::fs::read(qq_exe_path)?;
+    let Object::PE(qq_exe) = Object::parse(&qq_exe_data)? else {
+        return Err(format!("{qq_exe_path:?}不是PE文件").into());
+    };
+    let Object::PE(dll) = Object::parse(dll_data).map_err(|err| format!("修补文件不是有效的PE文件: {err}"))? else {
+        return Err("修补文件不是PE文件".into());
+    };
+    if !dll.is_lib {
+        return Err("修补文件不是dll".into());
+    }
+    let qq_machine = qq_exe.header.coff_header.machine;
+    let dll_machine = dll.header.coff_header.machine;
+    if qq_machine != dll_machine {
+        return Err(format!("修补文件的架构为{}，与QQ的架构{}不一致", machine_to_str(dll_machine), machine_to_str(qq_machine)).into());
+    }
+    for name in PATCH_EXPORTS {
+        if !dll.exports.iter().any(|export| export.name == Some(name)) {
+            return Err(format!("修补文件没有导出{name}").into());
+        }
+    }
+    log::info!("修补文件检查通过: dll {}", machine_to_str(dll_machine));
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn current() -> Box<dyn Platform> {
+    Box::new(windows::WindowsPlatform)
+}
+
+#[cfg(target_os = "linux")]
+pub fn current() -> Box<dyn Platform> {
+    Box::new(linux::LinuxPlatform)
+}
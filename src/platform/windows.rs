@@ -0,0 +1,139 @@
+use std::mem::{size_of, zeroed};
+use std::path::{Path, PathBuf};
+use std::ptr::null_mut;
+use std::str::FromStr;
+
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+use winapi::um::securitybaseapi::GetTokenInformation;
+use winapi::um::winnt::{TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+
+use super::{Platform, QQLayout};
+
+pub struct WindowsPlatform;
+
+fn get_qq_path_by_reg() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let hkcu = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE);
+    let qq_setting;
+    if let Ok(val) = hkcu.open_subkey(r#"Software\Microsoft\Windows\CurrentVersion\Uninstall\QQ"#) {
+        qq_setting = val;
+    } else {
+        qq_setting = hkcu.open_subkey(r#"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall\QQ"#)?;
+    }
+    let qq_path: String = qq_setting.get_value("UninstallString")?;
+    let q = PathBuf::from_str(&qq_path)?
+        .parent()
+        .ok_or("can't find qq path")?
+        .to_owned();
+    Ok(q)
+}
+
+fn is_admin() -> Result<bool, Box<dyn std::error::Error>> {
+    let mut token: winapi::um::winnt::HANDLE = null_mut();
+    let process = unsafe { GetCurrentProcess() };
+
+    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } != 0 {
+        let mut elevation: TOKEN_ELEVATION = unsafe { zeroed() };
+        let mut ret_length = 0;
+
+        let success = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                &mut elevation as *mut _ as winapi::shared::minwindef::LPVOID,
+                size_of::<TOKEN_ELEVATION>() as u32,
+                &mut ret_length,
+            )
+        };
+
+        unsafe { CloseHandle(token) };
+
+        if success != 0 && elevation.TokenIsElevated != 0 {
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    } else {
+        Ok(false)
+    }
+}
+
+fn is_x86_64(exe_data: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+    use goblin::Object;
+    match Object::parse(exe_data)? {
+        Object::PE(pe) => Ok(pe.is_64),
+        _ => Err("File is not a Windows PE file.".into()),
+    }
+}
+
+fn iswin32(qq_exe_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let content = std::fs::read(qq_exe_path)?;
+    if is_x86_64(&content)? {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+impl Platform for WindowsPlatform {
+    fn qq_exe_name(&self) -> &'static str {
+        "QQ.exe"
+    }
+
+    fn find_qq_path(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let qq_path = get_qq_path_by_reg()?;
+        log::info!("从注册表获取到QQ.exe");
+        Ok(qq_path)
+    }
+
+    fn qq_layout(&self, qq_path: &Path) -> QQLayout {
+        // 9.9.15之前没有versions目录，resources/app直接在安装目录下
+        let has_versions = qq_path.join("versions").join("config.json").is_file();
+        if !has_versions && qq_path.join("resources").join("app").join("package.json").is_file() {
+            QQLayout::Legacy
+        } else {
+            QQLayout::Current
+        }
+    }
+
+    fn qq_version(&self, qq_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+        if self.qq_layout(qq_path) == QQLayout::Legacy {
+            let package_json_path = qq_path.join("resources").join("app").join("package.json");
+            let package_str = std::fs::read_to_string(package_json_path)?;
+            let package_json: serde_json::Value = serde_json::from_str(&package_str)?;
+            let version = package_json["version"].as_str().ok_or("解析package.json失败")?;
+            return Ok(version.to_owned());
+        }
+        let config_json_path = qq_path.join("versions").join("config.json");
+        let config_str = std::fs::read_to_string(config_json_path)?;
+        let config_json: serde_json::Value = serde_json::from_str(&config_str)?;
+        let cur_version = config_json["curVersion"].as_str().ok_or("解析config.json失败")?;
+        Ok(cur_version.to_owned())
+    }
+
+    fn qq_inner_path(&self, qq_path: &Path, qq_version: &str) -> PathBuf {
+        if self.qq_layout(qq_path) == QQLayout::Legacy {
+            return qq_path.join("resources").join("app");
+        }
+        qq_path.join("versions").join(qq_version).join("resources").join("app")
+    }
+
+    fn has_privilege(&self, _qq_inner_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        is_admin()
+    }
+
+    fn user_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(PathBuf::from_str(&std::env::var("USERPROFILE")?)?)
+    }
+
+    fn cache_dir(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(PathBuf::from_str(&std::env::var("LOCALAPPDATA")?)?.join("llob_install").join("cache"))
+    }
+
+    fn patch_artifact_name(&self, qq_path: &Path) -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+        if iswin32(&qq_path.join(self.qq_exe_name()))? {
+            Ok(Some("dbghelp_x86.dll"))
+        } else {
+            Ok(Some("dbghelp_x64.dll"))
+        }
+    }
+}
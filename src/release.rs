@@ -0,0 +1,463 @@
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    mirror::Mirror,
+    source::{Component, Pin},
+};
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 6.1; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/89.0.4389.72 Safari/537.36";
+
+/// github上的下载地址以此开头，下载时会换成镜像
+pub const GITHUB_URL: &str = "https://github.com/";
+/// 本地文件的地址以此开头
+const FILE_URL: &str = "file://";
+/// 本地目录中每个版本的校验文件，格式和 sha256sum 的输出相同
+const SHA256SUMS_NAME: &str = "SHA256SUMS";
+
+/// release中的一个文件
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+    pub sha256: Option<String>,
+}
+
+/// 查到的一个release
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub tag: String,
+    pub assets: Vec<Asset>,
+    /// tag对应的源码包，LiteLoaderQQNT使用它
+    pub source_url: Option<String>,
+}
+
+impl Release {
+    /// 按顺序用每个模式匹配assets，返回第一个匹配的文件
+    pub fn find_asset(&self, patterns: &[String]) -> Option<&Asset> {
+        patterns
+            .iter()
+            .find_map(|pattern| self.assets.iter().find(|asset| glob_match(pattern, &asset.name)))
+    }
+
+    /// 所有文件名，用于出错时提示
+    pub fn asset_names(&self) -> String {
+        if self.assets.is_empty() {
+            return "无".to_owned();
+        }
+        self.assets.iter().map(|asset| asset.name.as_str()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// 文件名匹配，*匹配任意个字符，?匹配一个字符
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 上一个*的位置，以及它当时匹配到的name位置
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 地址是本地文件时返回它的路径
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix(FILE_URL).map(PathBuf::from)
+}
+
+fn file_url(path: &Path) -> String {
+    format!("{FILE_URL}{}", path.to_string_lossy())
+}
+
+/// 按版本号比较，数字部分按数值比较，例如 v3.10.0 > v3.9.1
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> Vec<(bool, &str)> {
+        let mut parts = vec![];
+        let mut start = 0;
+        let bytes = version.as_bytes();
+        for i in 1..=bytes.len() {
+            if i == bytes.len() || bytes[i].is_ascii_digit() != bytes[start].is_ascii_digit() {
+                parts.push((bytes[start].is_ascii_digit(), &version[start..i]));
+                start = i;
+            }
+        }
+        parts
+    }
+    for (a, b) in split(a).iter().zip(split(b).iter()) {
+        let ordering = match (a, b) {
+            ((true, a), (true, b)) => a.parse::<u64>().unwrap_or(0).cmp(&b.parse::<u64>().unwrap_or(0)),
+            ((_, a), (_, b)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    split(a).len().cmp(&split(b).len())
+}
+
+/// 查询组件有哪些版本，以及每个版本的文件在哪里
+pub trait ReleaseBackend {
+    /// 用于日志
+    fn describe(&self) -> String;
+
+    /// 最新的release
+    fn latest(&self, component: Component) -> Result<Release, Box<dyn std::error::Error>>;
+
+    /// 指定tag的release，不存在时返回错误
+    fn tag(&self, component: Component, tag: &str) -> Result<Release, Box<dyn std::error::Error>>;
+
+    /// 固定到提交或分支时的源码包地址，只有github支持
+    fn pinned_source(&self, _component: Component, _pin: &Pin) -> Result<String, Box<dyn std::error::Error>> {
+        Err(format!("{}只支持固定到tag", self.describe()).into())
+    }
+
+    /// release的信息中没有文件列表时使用的地址
+    fn default_asset_url(&self, _component: Component, _tag: &str, _file_name: &str) -> Option<String> {
+        None
+    }
+}
+
+/// 配置文件中 release_sources 的一项
+#[derive(Debug, Clone)]
+pub enum BackendConfig {
+    GitHub,
+    /// gitee和gitcode的api是一样的
+    Gitee {
+        name: &'static str,
+        api: String,
+        repo: Option<String>,
+        /// 源码包地址，{repo}和{tag}会被替换
+        archive: Option<String>,
+    },
+    /// 一个json清单，可以是网址也可以是本地文件
+    Manifest { location: String },
+    /// 本地目录，每个版本一个子目录
+    Local { dir: PathBuf },
+}
+
+impl BackendConfig {
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let kind = value["type"].as_str().or(value.as_str()).ok_or(format!("release_sources中缺少type: {value}"))?;
+        let repo = value["repo"].as_str().map(|repo| repo.to_owned());
+        match kind {
+            "github" => Ok(BackendConfig::GitHub),
+            "gitee" => Ok(BackendConfig::Gitee {
+                name: "Gitee",
+                api: value["api"].as_str().unwrap_or("https://gitee.com/api/v5").to_owned(),
+                repo,
+                archive: Some(value["archive"].as_str().unwrap_or("https://gitee.com/{repo}/repository/archive/{tag}.zip").to_owned()),
+            }),
+            "gitcode" => Ok(BackendConfig::Gitee {
+                name: "GitCode",
+                api: value["api"].as_str().unwrap_or("https://api.gitcode.com/api/v5").to_owned(),
+                repo,
+                archive: value["archive"].as_str().map(|archive| archive.to_owned()),
+            }),
+            "manifest" => Ok(BackendConfig::Manifest {
+                location: value["url"].as_str().ok_or("manifest需要url")?.to_owned(),
+            }),
+            "local" => Ok(BackendConfig::Local {
+                dir: PathBuf::from(value["path"].as_str().ok_or("local需要path")?),
+            }),
+            _ => Err(format!("未知的release来源: {kind}，只能是github、gitee、gitcode、manifest或local").into()),
+        }
+    }
+
+    pub fn is_github(&self) -> bool {
+        matches!(self, BackendConfig::GitHub)
+    }
+
+    /// api_mirror 是访问github api使用的镜像，没有可用的镜像时为None
+    pub fn build(self, rt_ptr: Arc<tokio::runtime::Runtime>, api_mirror: Option<Mirror>) -> Box<dyn ReleaseBackend> {
+        match self {
+            BackendConfig::GitHub => Box::new(GitHubBackend { rt_ptr, api_mirror }),
+            BackendConfig::Gitee { name, api, repo, archive } => Box::new(GiteeBackend {
+                rt_ptr,
+                name,
+                api,
+                repo,
+                archive,
+            }),
+            BackendConfig::Manifest { location } => Box::new(ManifestBackend { rt_ptr, location }),
+            BackendConfig::Local { dir } => Box::new(LocalBackend { dir }),
+        }
+    }
+}
+
+/// github和gitee的release json格式基本相同
+fn parse_release(json: &serde_json::Value, source_url: Option<String>) -> Result<Release, Box<dyn std::error::Error>> {
+    let tag = json["tag_name"].as_str().ok_or_else(|| json["message"].as_str().unwrap_or("Failed to get tag_name").to_owned())?;
+    let assets = json["assets"]
+        .as_array()
+        .map(|assets| {
+            assets
+                .iter()
+                .filter_map(|asset| {
+                    Some(Asset {
+                        name: asset["name"].as_str()?.to_owned(),
+                        url: asset["browser_download_url"].as_str()?.to_owned(),
+                        sha256: asset["digest"].as_str().and_then(|digest| digest.strip_prefix("sha256:")).map(|digest| digest.to_owned()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Release {
+        tag: tag.to_owned(),
+        assets,
+        source_url: source_url.map(|url| url.replace("{tag}", tag)),
+    })
+}
+
+fn get_json(rt_ptr: &Arc<tokio::runtime::Runtime>, url: &str) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let bin = crate::http_post(rt_ptr.clone(), url, Some(USER_AGENT))?;
+    Ok(serde_json::from_slice(&bin)?)
+}
+
+/// 通过github api查询，下载地址会在下载时换成镜像
+pub struct GitHubBackend {
+    rt_ptr: Arc<tokio::runtime::Runtime>,
+    api_mirror: Option<Mirror>,
+}
+
+impl GitHubBackend {
+    fn api_url(&self, path: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mirror = self.api_mirror.as_ref().ok_or("没有可用的github镜像")?;
+        Ok(mirror.api_url(path))
+    }
+
+    fn source_url(component: Component) -> String {
+        format!("{GITHUB_URL}{}/archive/refs/tags/{{tag}}.zip", component.repo())
+    }
+}
+
+impl ReleaseBackend for GitHubBackend {
+    fn describe(&self) -> String {
+        "GitHub".to_owned()
+    }
+
+    fn latest(&self, component: Component) -> Result<Release, Box<dyn std::error::Error>> {
+        let url = self.api_url(&format!("repos/{}/releases/latest", component.repo()))?;
+        let json = match get_json(&self.rt_ptr, &url) {
+            Ok(json) => json,
+            Err(err) => {
+                log::warn!("无法访问GitHub({err})，尝试使用备用URL");
+                let backup_url = format!("https://api.hydroroll.team/api/version?repo={}&type=github-releases-latest", component.repo());
+                get_json(&self.rt_ptr, &backup_url).map_err(|_| err)?
+            }
+        };
+        parse_release(&json, Some(Self::source_url(component)))
+    }
+
+    fn tag(&self, component: Component, tag: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        let url = self.api_url(&format!("repos/{}/releases/tags/{tag}", component.repo()))?;
+        parse_release(&get_json(&self.rt_ptr, &url)?, Some(Self::source_url(component)))
+    }
+
+    fn pinned_source(&self, component: Component, pin: &Pin) -> Result<String, Box<dyn std::error::Error>> {
+        match pin {
+            Pin::Tag(tag) => Ok(Self::source_url(component).replace("{tag}", tag)),
+            Pin::Commit(sha) => {
+                let url = self.api_url(&format!("repos/{}/commits/{sha}", component.repo()))?;
+                let json = get_json(&self.rt_ptr, &url)?;
+                if json["sha"].as_str().is_none() {
+                    return Err(json["message"].as_str().unwrap_or("未知错误").into());
+                }
+                Ok(format!("{GITHUB_URL}{}/archive/{sha}.zip", component.repo()))
+            }
+            Pin::Branch(branch) => Ok(format!("{GITHUB_URL}{}/archive/refs/heads/{branch}.zip", component.repo())),
+        }
+    }
+
+    fn default_asset_url(&self, component: Component, tag: &str, file_name: &str) -> Option<String> {
+        // 备用URL返回的信息中没有assets，只能拼出地址
+        Some(format!("{GITHUB_URL}{}/releases/download/{tag}/{file_name}", component.repo()))
+    }
+}
+
+/// gitee和gitcode，api都是 {api}/repos/{repo}/releases/...
+pub struct GiteeBackend {
+    rt_ptr: Arc<tokio::runtime::Runtime>,
+    name: &'static str,
+    api: String,
+    repo: Option<String>,
+    archive: Option<String>,
+}
+
+impl GiteeBackend {
+    fn repo(&self, component: Component) -> &str {
+        self.repo.as_deref().unwrap_or(component.repo())
+    }
+
+    fn get_release(&self, component: Component, path: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        let repo = self.repo(component);
+        let url = format!("{}/repos/{repo}/releases/{path}", self.api.trim_end_matches('/'));
+        let archive = self.archive.as_ref().map(|archive| archive.replace("{repo}", repo));
+        parse_release(&get_json(&self.rt_ptr, &url)?, archive)
+    }
+}
+
+impl ReleaseBackend for GiteeBackend {
+    fn describe(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn latest(&self, component: Component) -> Result<Release, Box<dyn std::error::Error>> {
+        self.get_release(component, "latest")
+    }
+
+    fn tag(&self, component: Component, tag: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        self.get_release(component, &format!("tags/{tag}"))
+    }
+}
+
+/// json清单，格式为 {"releases": [{"tag": ..., "assets": [{"name", "url", "sha256"}], "source_url": ...}]}
+/// 第一个release是最新的，多个组件共用一个清单时放在 {"components": {"组件名": {...}}} 中
+pub struct ManifestBackend {
+    rt_ptr: Arc<tokio::runtime::Runtime>,
+    location: String,
+}
+
+impl ManifestBackend {
+    fn releases(&self, component: Component) -> Result<Vec<Release>, Box<dyn std::error::Error>> {
+        let is_url = self.location.starts_with("http://") || self.location.starts_with("https://");
+        let json: serde_json::Value = if is_url {
+            get_json(&self.rt_ptr, &self.location)?
+        } else {
+            serde_json::from_str(&std::fs::read_to_string(&self.location).map_err(|err| format!("无法读取{}: {err}", self.location))?)?
+        };
+        let json = if json["components"].is_object() { &json["components"][component.name()] } else { &json };
+        // 本地清单中的相对路径相对于清单所在的目录
+        let base_dir = Path::new(&self.location).parent().unwrap_or(Path::new("."));
+        let resolve = |url: &str| {
+            if is_url || url.contains("://") {
+                url.to_owned()
+            } else {
+                file_url(&base_dir.join(url))
+            }
+        };
+        let releases = json["releases"].as_array().ok_or(format!("{}中没有{}的releases", self.location, component.name()))?;
+        releases
+            .iter()
+            .map(|release| {
+                let tag = release["tag"].as_str().ok_or("清单中的release缺少tag")?;
+                let assets = release["assets"]
+                    .as_array()
+                    .map(|assets| {
+                        assets
+                            .iter()
+                            .filter_map(|asset| {
+                                Some(Asset {
+                                    name: asset["name"].as_str()?.to_owned(),
+                                    url: resolve(asset["url"].as_str()?),
+                                    sha256: asset["sha256"].as_str().map(|sha256| sha256.to_owned()),
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(Release {
+                    tag: tag.to_owned(),
+                    assets,
+                    source_url: release["source_url"].as_str().map(resolve),
+                })
+            })
+            .collect()
+    }
+}
+
+impl ReleaseBackend for ManifestBackend {
+    fn describe(&self) -> String {
+        format!("清单{}", self.location)
+    }
+
+    fn latest(&self, component: Component) -> Result<Release, Box<dyn std::error::Error>> {
+        self.releases(component)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("{}中没有任何release", self.location).into())
+    }
+
+    fn tag(&self, component: Component, tag: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        self.releases(component)?
+            .into_iter()
+            .find(|release| release.tag == tag)
+            .ok_or_else(|| format!("{}中没有{tag}", self.location).into())
+    }
+}
+
+/// 本地目录，dir/<tag>/<文件>，版本号最大的是最新的
+pub struct LocalBackend {
+    dir: PathBuf,
+}
+
+impl LocalBackend {
+    fn release(&self, tag: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        let tag_dir = self.dir.join(tag);
+        if !tag_dir.is_dir() {
+            return Err(format!("{tag_dir:?}不存在").into());
+        }
+        let sums = std::fs::read_to_string(tag_dir.join(SHA256SUMS_NAME)).unwrap_or_default();
+        let mut assets = vec![];
+        for entry in std::fs::read_dir(&tag_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_file() || name == SHA256SUMS_NAME {
+                continue;
+            }
+            let sha256 = sums.lines().find_map(|line| {
+                let (sha256, file) = line.split_once(char::is_whitespace)?;
+                (file.trim().trim_start_matches('*') == name).then(|| sha256.to_owned())
+            });
+            assets.push(Asset {
+                name,
+                url: file_url(&entry.path()),
+                sha256,
+            });
+        }
+        Ok(Release {
+            tag: tag.to_owned(),
+            assets,
+            source_url: None,
+        })
+    }
+}
+
+impl ReleaseBackend for LocalBackend {
+    fn describe(&self) -> String {
+        format!("本地目录{:?}", self.dir)
+    }
+
+    fn latest(&self, _component: Component) -> Result<Release, Box<dyn std::error::Error>> {
+        let mut tags = vec![];
+        for entry in std::fs::read_dir(&self.dir).map_err(|err| format!("无法读取{:?}: {err}", self.dir))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                tags.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        let tag = tags.into_iter().max_by(|a, b| compare_versions(a, b)).ok_or(format!("{:?}中没有任何版本", self.dir))?;
+        self.release(&tag)
+    }
+
+    fn tag(&self, _component: Component, tag: &str) -> Result<Release, Box<dyn std::error::Error>> {
+        self.release(tag)
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cache::Cache,
+    mirror::{self, Mirror},
+    net,
+    release::{self, BackendConfig, Release, ReleaseBackend},
+};
+
+/// 下载中的文件放在缓存目录下的这个目录中
+pub const DOWNLOAD_DIR_NAME: &str = "downloads";
+
+/// 离线包中的清单文件
+pub const BUNDLE_MANIFEST_NAME: &str = "llob_bundle.json";
+
+/// 安装需要的组件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+    VerifyPatch,
+    LiteLoader,
+    LLOneBot,
+}
+
+impl Component {
+    pub const ALL: [Component; 3] = [Component::VerifyPatch, Component::LiteLoader, Component::LLOneBot];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::VerifyPatch => "QQNTFileVerifyPatch",
+            Component::LiteLoader => "LiteLoaderQQNT",
+            Component::LLOneBot => "LLOneBot",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Component::ALL
+            .into_iter()
+            .find(|component| component.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn repo(&self) -> &'static str {
+        match self {
+            Component::VerifyPatch => "LiteLoaderQQNT/QQNTFileVerifyPatch",
+            Component::LiteLoader => "LiteLoaderQQNT/LiteLoaderQQNT",
+            Component::LLOneBot => "LLOneBot/LLOneBot",
+        }
+    }
+}
+
+/// 组件固定的版本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    Tag(String),
+    Commit(String),
+    /// 跟踪某个分支的最新代码，只有LiteLoaderQQNT支持
+    Branch(String),
+}
+
+impl Pin {
+    /// "commit:<sha>" 表示固定到某个提交，其它都当作tag
+    pub fn parse(value: &str) -> Self {
+        match value.strip_prefix("commit:") {
+            Some(sha) => Pin::Commit(sha.to_owned()),
+            None => Pin::Tag(value.to_owned()),
+        }
+    }
+
+    pub fn version(&self) -> &str {
+        match self {
+            Pin::Tag(tag) => tag,
+            Pin::Commit(sha) => sha,
+            Pin::Branch(branch) => branch,
+        }
+    }
+}
+
+pub type Pins = HashMap<Component, Pin>;
+
+/// LiteLoaderQQNT 在离线包中的文件名
+pub const LITELOADER_FILE_NAME: &str = "LiteLoaderQQNT.zip";
+/// LLOneBot 的文件名
+pub const LLONEBOT_FILE_NAME: &str = "LLOneBot.zip";
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// 获取到的文件
+pub struct Artifact {
+    pub version: String,
+    pub data: Vec<u8>,
+    /// 来源提供的sha256，没有时为None
+    pub sha256: Option<String>,
+}
+
+/// 配置文件中指定的校验值
+#[derive(Debug, Clone, Default)]
+pub struct Checksums {
+    /// 文件名 -> sha256
+    pub files: HashMap<String, String>,
+    /// 为true时没有任何可用的sha256也拒绝安装
+    pub required: bool,
+}
+
+/// 获取文件，并用来源提供的和配置文件中指定的sha256校验，任何一个不一致都会失败
+pub fn fetch_verified(source: &dyn Source, component: Component, file_name: &str, checksums: &Checksums) -> Result<Artifact, Box<dyn std::error::Error>> {
+    let artifact = source.fetch(component, file_name)?;
+    let expected: Vec<&str> = artifact
+        .sha256
+        .as_deref()
+        .into_iter()
+        .chain(checksums.files.get(file_name).map(|sha256| sha256.as_str()))
+        .collect();
+    if expected.is_empty() && checksums.required {
+        return Err(format!("{file_name}没有可用的sha256，拒绝安装。请在llob_install.json的sha256中指定它的校验值").into());
+    }
+    if expected.is_empty() {
+        log::warn!("{file_name}没有可用的sha256，跳过校验");
+        return Ok(artifact);
+    }
+    let actual = sha256_hex(&artifact.data);
+    for expected in expected {
+        if !expected.eq_ignore_ascii_case(&actual) {
+            return Err(format!("{file_name}校验失败，拒绝安装。期望的sha256为{expected}，实际为{actual}").into());
+        }
+    }
+    log::info!("{file_name}校验通过 sha256: {actual}");
+    Ok(artifact)
+}
+
+/// 安装需要的文件从哪里来
+pub trait Source {
+    /// 获取组件中名为 file_name 的文件
+    fn fetch(&self, component: Component, file_name: &str) -> Result<Artifact, Box<dyn std::error::Error>>;
+}
+
+/// 访问网络的设置
+pub struct NetworkOptions {
+    pub mirrors: Vec<Mirror>,
+    /// 忽略缓存，重新对镜像测速
+    pub refresh_mirrors: bool,
+    /// 所有镜像都下载失败后重试的次数
+    pub retries: u32,
+    /// 第一次重试前等待的时间，之后每次翻倍
+    pub backoff: Duration,
+    /// 下载缓存，为None时不使用缓存
+    pub cache: Option<Cache>,
+    /// 镜像测速结果的缓存文件
+    pub mirror_cache: PathBuf,
+    /// 下载中的文件所在的目录，每个用户一个，不能放在公共的临时目录中
+    pub download_dir: PathBuf,
+    /// 每个组件从哪里查询版本，没有配置的使用github
+    pub release_sources: HashMap<Component, BackendConfig>,
+    /// 每个文件在release中的文件名模式，没有配置的只匹配同名文件
+    pub asset_patterns: AssetPatterns,
+}
+
+/// 文件名 -> release中的文件名模式，按顺序匹配
+pub type AssetPatterns = HashMap<String, Vec<String>>;
+
+/// 检查下载到的内容，不对时换一个镜像
+fn check_payload(file_name: &str, data: &[u8], sha256: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    if data.is_empty() {
+        return Err("内容为空".into());
+    }
+    if let Some(sha256) = sha256 {
+        if !sha256.eq_ignore_ascii_case(&sha256_hex(data)) {
+            return Err("sha256不一致".into());
+        }
+    }
+    if file_name.ends_with(".zip") && !data.starts_with(b"PK") {
+        return Err("不是zip文件".into());
+    }
+    if file_name.ends_with(".dll") && !data.starts_with(b"MZ") {
+        return Err("不是dll文件".into());
+    }
+    Ok(())
+}
+
+/// 从github(或镜像)以及配置的其它来源下载
+pub struct NetworkSource {
+    rt_ptr: Arc<tokio::runtime::Runtime>,
+    /// 测速后排好序的镜像，API使用第一个，下载时依次尝试
+    mirrors: Vec<Mirror>,
+    /// 下载失败过的镜像，之后的下载把它们放到最后
+    failed: RefCell<HashSet<usize>>,
+    retries: u32,
+    backoff: Duration,
+    cache: Option<Cache>,
+    download_dir: PathBuf,
+    pins: Pins,
+    /// 每个组件从哪里查询版本
+    backends: HashMap<Component, Box<dyn ReleaseBackend>>,
+    // 同一个组件的多个文件只查一次版本号，这里缓存查到的release
+    releases: RefCell<HashMap<Component, Release>>,
+    asset_patterns: AssetPatterns,
+}
+
+impl NetworkSource {
+    pub fn new(rt_ptr: Arc<tokio::runtime::Runtime>, options: NetworkOptions, pins: Pins) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut configs: HashMap<Component, BackendConfig> = Component::ALL
+            .iter()
+            .map(|component| (*component, options.release_sources.get(component).cloned().unwrap_or(BackendConfig::GitHub)))
+            .collect();
+        // 只有用到github时才需要镜像
+        let mirrors = if !configs.values().any(|config| config.is_github()) {
+            vec![]
+        } else {
+            match mirror::rank(&rt_ptr, &options.mirrors, options.refresh_mirrors, &options.mirror_cache) {
+                Ok(mirrors) => {
+                    log::info!("使用镜像: {}", mirrors[0]);
+                    mirrors
+                }
+                // 有缓存时不能访问网络也可以安装
+                Err(err) if options.cache.is_some() => {
+                    log::warn!("{err}，只能使用缓存中的文件");
+                    vec![]
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        let backends = configs
+            .drain()
+            .map(|(component, config)| {
+                let is_github = config.is_github();
+                let backend = config.build(rt_ptr.clone(), mirrors.first().cloned());
+                if !is_github {
+                    log::info!("{}使用{}", component.name(), backend.describe());
+                }
+                (component, backend)
+            })
+            .collect();
+        Ok(NetworkSource {
+            rt_ptr,
+            mirrors,
+            failed: RefCell::new(HashSet::new()),
+            retries: options.retries,
+            backoff: options.backoff,
+            cache: options.cache,
+            download_dir: options.download_dir,
+            pins,
+            backends,
+            releases: RefCell::new(HashMap::new()),
+            asset_patterns: options.asset_patterns,
+        })
+    }
+
+    fn backend(&self, component: Component) -> &dyn ReleaseBackend {
+        &*self.backends[&component]
+    }
+
+    /// 查询要安装的release，固定了tag时查询这个tag，否则查询最新的
+    fn release(&self, component: Component) -> Result<Release, Box<dyn std::error::Error>> {
+        if let Some(release) = self.releases.borrow().get(&component) {
+            return Ok(release.clone());
+        }
+        let backend = self.backend(component);
+        let release = match self.pins.get(&component) {
+            Some(pin) => {
+                log::info!("正在检查{}固定的版本{}...", component.name(), pin.version());
+                backend
+                    .tag(component, pin.version())
+                    .map_err(|err| format!("{}固定的版本{}不存在或无法访问: {err}", component.name(), pin.version()))?
+            }
+            None => {
+                log::info!("正在获取最新{}版本号...", component.name());
+                match backend.latest(component) {
+                    Ok(release) => {
+                        log::info!("最新{}版本号:{}", component.name(), release.tag);
+                        release
+                    }
+                    Err(err) => {
+                        let err = format!("无法获取最新{}版本号: {err}", component.name());
+                        let version = self.cache.as_ref().and_then(|cache| cache.latest_version(component)).ok_or(err)?;
+                        log::warn!("无法获取最新{}版本号，使用缓存中的{version}", component.name());
+                        Release {
+                            tag: version,
+                            assets: vec![],
+                            source_url: None,
+                        }
+                    }
+                }
+            }
+        };
+        self.releases.borrow_mut().insert(component, release.clone());
+        Ok(release)
+    }
+
+    /// github上的文件依次尝试所有镜像，其它地址只有一个，本地文件直接读取
+    fn candidates(&self, url: &str) -> Vec<(Option<usize>, String)> {
+        match url.strip_prefix(release::GITHUB_URL) {
+            Some(path) => {
+                let mut order: Vec<usize> = (0..self.mirrors.len()).collect();
+                order.sort_by_key(|index| self.failed.borrow().contains(index));
+                order.into_iter().map(|index| (Some(index), self.mirrors[index].download_url(path))).collect()
+            }
+            None => vec![(None, url.to_owned())],
+        }
+    }
+
+    /// 依次尝试所有镜像，失败或内容不对时换下一个，一轮都失败后等待一段时间再重试
+    fn download(&self, component: Component, file_name: &str, url: &str, sha256: Option<&str>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if let Some(path) = release::local_path(url) {
+            let data = std::fs::read(&path).map_err(|err| format!("无法读取{path:?}: {err}"))?;
+            check_payload(file_name, &data, sha256)?;
+            log::info!("{file_name}来自{path:?}");
+            return Ok(data);
+        }
+        if self.candidates(url).is_empty() {
+            return Err(format!("{}下载失败，没有可用的github镜像", component.name()).into());
+        }
+        let mut backoff = self.backoff;
+        let mut last_err = String::new();
+        for round in 0..=self.retries {
+            if round > 0 {
+                log::warn!("所有镜像都下载失败，{:.1}秒后第{round}次重试...", backoff.as_secs_f64());
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            for (index, candidate) in self.candidates(url) {
+                let from = match index {
+                    Some(index) => format!("镜像{}", self.mirrors[index]),
+                    None => candidate.clone(),
+                };
+                // 不同镜像上的文件不一定相同，只从同一个地址的断点继续
+                let part_path = self.download_dir.join(format!("{}.part", &sha256_hex(candidate.as_bytes())[..16]));
+                let ret = net::download(&self.rt_ptr, &candidate, &part_path)
+                    .and_then(|data| check_payload(file_name, &data, sha256).map(|_| data));
+                match ret {
+                    Ok(data) => {
+                        log::info!("{file_name}由{from}提供");
+                        return Ok(data);
+                    }
+                    Err(err) => {
+                        log::warn!("从{from}下载{file_name}失败: {err}");
+                        last_err = err.to_string();
+                        if let Some(index) = index {
+                            self.failed.borrow_mut().insert(index);
+                        }
+                    }
+                }
+            }
+        }
+        Err(format!("{}下载失败，已尝试所有镜像: {last_err}", component.name()).into())
+    }
+
+    /// 从缓存中读取，缓存中的文件和来源提供的sha256不一致时不使用
+    fn cached(&self, component: Component, version: &str, file_name: &str) -> Option<Artifact> {
+        let sha256 = self.asset_digest(component, file_name);
+        let data = self.cache.as_ref()?.get(component, version, file_name, sha256.as_deref())?;
+        Some(Artifact {
+            version: version.to_owned(),
+            data,
+            sha256,
+        })
+    }
+
+    fn patterns(&self, file_name: &str) -> Vec<String> {
+        self.asset_patterns.get(file_name).cloned().unwrap_or_else(|| vec![file_name.to_owned()])
+    }
+
+    /// 来源在release的assets中提供的sha256
+    fn asset_digest(&self, component: Component, file_name: &str) -> Option<String> {
+        self.releases.borrow().get(&component)?.find_asset(&self.patterns(file_name))?.sha256.clone()
+    }
+
+    /// 从release的assets中找到要下载的文件，找不到时列出所有文件
+    fn asset_url(&self, component: Component, release: &Release, file_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let patterns = self.patterns(file_name);
+        if let Some(asset) = release.find_asset(&patterns) {
+            if asset.name != file_name {
+                log::info!("{file_name}使用{}中的{}", release.tag, asset.name);
+            }
+            return Ok(asset.url.clone());
+        }
+        if release.assets.is_empty() {
+            if let Some(url) = self.backend(component).default_asset_url(component, &release.tag, file_name) {
+                log::warn!("{}的{}没有文件列表，使用默认地址", component.name(), release.tag);
+                return Ok(url);
+            }
+        }
+        Err(format!(
+            "{}的{}中没有匹配{}的文件，可用的文件: {}",
+            component.name(),
+            release.tag,
+            patterns.join("、"),
+            release.asset_names()
+        )
+        .into())
+    }
+}
+
+impl Source for NetworkSource {
+    fn fetch(&self, component: Component, file_name: &str) -> Result<Artifact, Box<dyn std::error::Error>> {
+        let pin = self.pins.get(&component);
+        // 固定的版本已经在缓存中时，不需要访问网络
+        if let Some(pin @ (Pin::Tag(_) | Pin::Commit(_))) = pin {
+            if let Some(artifact) = self.cached(component, pin.version(), file_name) {
+                return Ok(artifact);
+            }
+        }
+        let (version, url, sha256) = match pin {
+            Some(pin @ (Pin::Commit(_) | Pin::Branch(_))) => {
+                if component != Component::LiteLoader {
+                    return Err(format!("{}只能固定到tag", component.name()).into());
+                }
+                if let Pin::Branch(branch) = pin {
+                    log::warn!("LiteLoaderQQNT使用{branch}分支的最新代码，可能不稳定");
+                } else {
+                    log::info!("正在检查{}固定的版本{}...", component.name(), pin.version());
+                }
+                let url = self
+                    .backend(component)
+                    .pinned_source(component, pin)
+                    .map_err(|err| format!("{}固定的版本{}不存在或无法访问: {err}", component.name(), pin.version()))?;
+                (pin.version().to_owned(), url, None)
+            }
+            _ => {
+                let release = self.release(component)?;
+                if pin.is_none() {
+                    if let Some(artifact) = self.cached(component, &release.tag, file_name) {
+                        return Ok(artifact);
+                    }
+                }
+                // LiteLoaderQQNT 优先使用release中的文件，它有来源提供的sha256，没有时使用tag对应的源码包
+                let source_url = match component {
+                    Component::LiteLoader if release.find_asset(&self.patterns(file_name)).is_none() => release.source_url.clone(),
+                    _ => None,
+                };
+                match source_url {
+                    // 源码包和release中的文件不是同一个文件，没有可用的sha256
+                    Some(source_url) => (release.tag, source_url, None),
+                    None => (release.tag.clone(), self.asset_url(component, &release, file_name)?, self.asset_digest(component, file_name)),
+                }
+            }
+        };
+        log::info!("正在下载{}...", component.name());
+        let data = self.download(component, file_name, &url, sha256.as_deref())?;
+        log::info!("下载完成");
+        // 分支的内容会变，不缓存
+        if let (Some(cache), false) = (&self.cache, matches!(pin, Some(Pin::Branch(_)))) {
+            if let Err(err) = cache.put(component, &version, file_name, &data) {
+                log::warn!("无法缓存{file_name}: {err}");
+            }
+        }
+        Ok(Artifact { version, data, sha256 })
+    }
+}
+
+/// 从离线包中读取，离线包可以是一个目录或者一个zip
+pub struct BundleSource {
+    path: PathBuf,
+    manifest: serde_json::Value,
+    pins: Pins,
+}
+
+impl BundleSource {
+    pub fn open(path: &Path, pins: Pins) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut bundle = BundleSource {
+            path: path.to_path_buf(),
+            manifest: serde_json::Value::Null,
+            pins,
+        };
+        let manifest = bundle.read_file(BUNDLE_MANIFEST_NAME).map_err(|err| format!("无法读取离线包{path:?}的清单: {err}"))?;
+        bundle.manifest = serde_json::from_slice(&manifest)?;
+        log::info!("使用离线包: {path:?}");
+        Ok(bundle)
+    }
+
+    pub fn manifest(&self) -> &serde_json::Value {
+        &self.manifest
+    }
+
+    fn read_file(&self, name: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.path.is_dir() {
+            return Ok(std::fs::read(self.path.join(name))?);
+        }
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&self.path)?)?;
+        let mut file = archive.by_name(name)?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl Source for BundleSource {
+    fn fetch(&self, component: Component, file_name: &str) -> Result<Artifact, Box<dyn std::error::Error>> {
+        let entry = &self.manifest["components"][component.name()];
+        let version = entry["version"]
+            .as_str()
+            .ok_or(format!("离线包中没有{}", component.name()))?;
+        if let Some(pin) = self.pins.get(&component) {
+            if pin.version() != version {
+                return Err(format!("离线包中{}的版本为{version}，与固定的版本{}不一致", component.name(), pin.version()).into());
+            }
+        }
+        let file = entry["files"]
+            .as_array()
+            .and_then(|files| files.iter().find(|file| file["name"] == file_name))
+            .ok_or(format!("离线包中没有{}的{file_name}", component.name()))?;
+        let data = self.read_file(file_name)?;
+        log::info!("从离线包中读取{} {version}", component.name());
+        Ok(Artifact {
+            version: version.to_owned(),
+            data,
+            sha256: file["sha256"].as_str().map(|sha256| sha256.to_owned()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 所有组件都从 root 下的 releases.json 查询
+    fn manifest_source(root: &Path, release: serde_json::Value) -> NetworkSource {
+        let manifest_path = root.join("releases.json");
+        let manifest = serde_json::json!({"components": {"LiteLoaderQQNT": {"releases": [release]}}});
+        std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let location = manifest_path.to_string_lossy().into_owned();
+        let options = NetworkOptions {
+            mirrors: vec![],
+            refresh_mirrors: false,
+            retries: 0,
+            backoff: Duration::ZERO,
+            cache: None,
+            mirror_cache: root.join("mirrors.json"),
+            download_dir: root.join(DOWNLOAD_DIR_NAME),
+            release_sources: Component::ALL
+                .iter()
+                .map(|component| (*component, BackendConfig::Manifest { location: location.clone() }))
+                .collect(),
+            asset_patterns: AssetPatterns::new(),
+        };
+        let rt_ptr = Arc::new(tokio::runtime::Runtime::new().unwrap());
+        NetworkSource::new(rt_ptr, options, Pins::new()).unwrap()
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("llob_install_test_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn liteloader_uses_release_asset_with_its_digest() {
+        let root = temp_root("asset_digest");
+        let asset = b"PK asset".to_vec();
+        std::fs::write(root.join(LITELOADER_FILE_NAME), &asset).unwrap();
+        std::fs::write(root.join("source.zip"), b"PK source archive").unwrap();
+        let source = manifest_source(&root, serde_json::json!({
+            "tag": "1.2.3",
+            "assets": [{"name": LITELOADER_FILE_NAME, "url": LITELOADER_FILE_NAME, "sha256": sha256_hex(&asset)}],
+            "source_url": "source.zip",
+        }));
+
+        let artifact = fetch_verified(&source, Component::LiteLoader, LITELOADER_FILE_NAME, &Checksums::default()).unwrap();
+        assert_eq!(artifact.version, "1.2.3");
+        assert_eq!(artifact.data, asset);
+        assert_eq!(artifact.sha256, Some(sha256_hex(&asset)));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn liteloader_source_archive_has_no_digest() {
+        let root = temp_root("source_archive");
+        let archive = b"PK source archive".to_vec();
+        std::fs::write(root.join("source.zip"), &archive).unwrap();
+        let source = manifest_source(&root, serde_json::json!({"tag": "1.2.3", "source_url": "source.zip"}));
+
+        let artifact = source.fetch(Component::LiteLoader, LITELOADER_FILE_NAME).unwrap();
+        assert_eq!(artifact.data, archive);
+        assert_eq!(artifact.sha256, None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{cli::Options, mirror, platform::Platform, source::BundleSource, LITELOADER_DIR_NAME};
+
+/// 读取json文件中的version字段
+fn read_version(json_path: &Path) -> Option<String> {
+    let json_str = std::fs::read_to_string(json_path).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&json_str).ok()?;
+    json["version"].as_str().map(|v| v.to_owned())
+}
+
+pub fn status(platform: &dyn Platform, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let qq = crate::locate_qq(platform, opts)?;
+
+    let patched = match platform.patch_artifact_name(&qq.qq_path)? {
+        Some(_) => Some(qq.qq_path.join(crate::platform::PATCH_FILE_NAME).is_file()),
+        None => None,
+    };
+    let injected = qq.is_injected();
+    let liteloader_path = platform.user_dir()?.join(LITELOADER_DIR_NAME);
+    let liteloader_version = read_version(&liteloader_path.join("package.json"));
+    let llonebot_version = read_version(&liteloader_path.join("plugins").join("LLOneBot").join("manifest.json"));
+    let qq_running = platform.is_qq_run(&qq.qq_path).ok();
+    let versions: Vec<serde_json::Value> = crate::other_versions(platform, &qq)
+        .iter()
+        .map(|other| {
+            let state = if other.is_newer_than(&qq) { "等待更新" } else { "旧版本" };
+            let injected = other.is_injected();
+            log::info!("QQ版本{}({state}): {}", other.qq_version, if injected { "已注入" } else { "未注入" });
+            serde_json::json!({
+                "version": other.qq_version,
+                "pending": other.is_newer_than(&qq),
+                "injected": injected,
+            })
+        })
+        .collect();
+
+    match patched {
+        Some(true) => log::info!("修补文件: 已安装"),
+        Some(false) => log::info!("修补文件: 未安装"),
+        None => log::info!("修补文件: 不需要"),
+    }
+    log::info!("LiteLoaderQQNT注入: {}", if injected { "已注入" } else { "未注入" });
+    log::info!("LiteLoaderQQNT版本: {}", liteloader_version.as_deref().unwrap_or("未安装"));
+    log::info!("LLOneBot版本: {}", llonebot_version.as_deref().unwrap_or("未安装"));
+    if let Some(qq_running) = qq_running {
+        log::info!("QQ运行中: {}", if qq_running { "是" } else { "否" });
+    }
+
+    Ok(serde_json::json!({
+        "qq_path": qq.qq_path,
+        "qq_version": qq.qq_version,
+        "layout": qq.layout.name(),
+        "patched": patched,
+        "injected": injected,
+        "other_versions": versions,
+        "liteloader_path": liteloader_path,
+        "liteloader_version": liteloader_version,
+        "llonebot_version": llonebot_version,
+        "qq_running": qq_running,
+    }))
+}
+
+/// 一项检查的结果
+struct Check {
+    name: &'static str,
+    ok: bool,
+    message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, ret: Result<String, String>) -> Self {
+        match ret {
+            Ok(message) => {
+                log::info!("[通过] {name}: {message}");
+                Check { name, ok: true, message }
+            }
+            Err(message) => {
+                log::error!("[失败] {name}: {message}");
+                Check { name, ok: false, message }
+            }
+        }
+    }
+}
+
+pub fn doctor(platform: &dyn Platform, rt_ptr: Arc<tokio::runtime::Runtime>, opts: &Options) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let mut checks = vec![];
+
+    checks.push(Check::new("env", match std::env::var("LITELOADERQQNT_PROFILE") {
+        Ok(_) => Err("环境变量中存在LITELOADERQQNT_PROFILE，可能已经手动安装过LiteLoaderQQNT".to_owned()),
+        Err(_) => Ok("环境变量正常".to_owned()),
+    }));
+
+    match crate::locate_qq(platform, opts) {
+        Ok(qq) => {
+            checks.push(Check::new("qq", Ok(format!("{:?} 版本 {}", qq.qq_path, qq.qq_version))));
+            checks.push(Check::new("privilege", match platform.has_privilege(&qq.qq_inner_path) {
+                Ok(true) => Ok("拥有修改QQ的权限".to_owned()),
+                Ok(false) => Err("没有修改QQ的权限，请以管理员身份运行(linux下请使用sudo)".to_owned()),
+                Err(err) => Err(format!("无法检查权限:{err}")),
+            }));
+            checks.push(Check::new("qq_running", match platform.is_qq_run(&qq.qq_path) {
+                Ok(false) => Ok("QQ未运行".to_owned()),
+                Ok(true) => Err("QQ正在运行，请先结束QQ".to_owned()),
+                Err(err) => Err(format!("无法检查QQ是否正在运行:{err}")),
+            }));
+            // 旧版QQ的加载脚本是安装时新建的，这里只检查QQ自己的入口
+            checks.push(Check::new("launcher", if qq.qq_inner_path.join("app_launcher").join("index.js").is_file() && qq.package_json_path.is_file() {
+                Ok(format!("{:?}", qq.qq_inner_path))
+            } else {
+                Err(format!("在{:?}中没有找到package.json或app_launcher/index.js", qq.qq_inner_path))
+            }));
+        }
+        Err(err) => checks.push(Check::new("qq", Err(err.to_string()))),
+    }
+
+    checks.push(Check::new("user_dir", match platform.user_dir() {
+        Ok(user_dir) => Ok(format!("{:?}", user_dir.join(LITELOADER_DIR_NAME))),
+        Err(err) => Err(format!("无法获取用户目录:{err}")),
+    }));
+
+    if let Some(bundle) = &opts.bundle {
+        // 离线安装不需要网络
+        checks.push(Check::new("bundle", match BundleSource::open(bundle, Default::default()) {
+            Ok(bundle) => {
+                let components = bundle.manifest()["components"]
+                    .as_object()
+                    .map(|components| {
+                        components
+                            .iter()
+                            .map(|(name, component)| format!("{name} {}", component["version"].as_str().unwrap_or("?")))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                    .unwrap_or_default();
+                Ok(format!("离线包中的组件: {components}"))
+            }
+            Err(err) => Err(err.to_string()),
+        }));
+    } else {
+        checks.push(Check::new("network", match crate::get_network_options(platform, opts) {
+            Ok(options) => match rt_ptr.block_on(mirror::measure_all(&options.mirrors)).first() {
+                Some(best) => Ok(format!("可以通过{}连接github", best.mirror)),
+                None => Err("无法连接github或任何镜像".to_owned()),
+            },
+            Err(err) => Err(format!("网络配置错误:{err}")),
+        }));
+    }
+
+    let ok = checks.iter().all(|check| check.ok);
+    if ok {
+        log::info!("所有检查均已通过");
+    }
+    Ok(serde_json::json!({
+        "ok": ok,
+        "checks": checks.iter().map(|check| serde_json::json!({
+            "name": check.name,
+            "ok": check.ok,
+            "message": check.message,
+        })).collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use crate::backup::Backup;
+
+/// 安装中的一步，执行前先备份它会修改的路径，失败时整个安装回滚
+pub struct Step<'a> {
+    pub name: &'static str,
+    /// 这一步会修改的路径，以及其中不需要备份的子目录
+    pub targets: Vec<(PathBuf, &'static [&'static str])>,
+    pub action: Box<dyn FnOnce() -> Result<(), Box<dyn std::error::Error>> + 'a>,
+}
+
+impl<'a> Step<'a> {
+    pub fn new(
+        name: &'static str,
+        targets: Vec<(PathBuf, &'static [&'static str])>,
+        action: impl FnOnce() -> Result<(), Box<dyn std::error::Error>> + 'a,
+    ) -> Self {
+        Step {
+            name,
+            targets,
+            action: Box::new(action),
+        }
+    }
+}
+
+/// 某一步失败(并已回滚)时返回的错误
+#[derive(Debug)]
+pub struct StepError {
+    pub step: &'static str,
+    pub message: String,
+    pub rolled_back: bool,
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.rolled_back {
+            write!(f, "步骤\"{}\"失败，已回滚所有修改: {}", self.step, self.message)
+        } else {
+            write!(f, "步骤\"{}\"失败，且回滚失败: {}", self.step, self.message)
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+fn run_step(backup: &mut Backup, step: Step) -> Result<(), Box<dyn std::error::Error>> {
+    for (path, exclude) in &step.targets {
+        backup.snapshot(path, exclude)?;
+    }
+    (step.action)()
+}
+
+/// 按顺序执行所有步骤，任何一步失败都会按相反的顺序撤销之前的修改
+pub fn run(backup: &mut Backup, steps: Vec<Step>) -> Result<(), StepError> {
+    let count = steps.len();
+    for (index, step) in steps.into_iter().enumerate() {
+        let name = step.name;
+        log::info!("[{}/{count}] {name}...", index + 1);
+        if let Err(err) = run_step(backup, step) {
+            log::error!("{name}失败: {err}，正在回滚...");
+            let rolled_back = match backup.restore() {
+                Ok(()) => {
+                    log::info!("回滚完成");
+                    // 已经回滚了，这个备份和安装前一样，没必要留着
+                    if let Err(remove_err) = backup.remove() {
+                        log::warn!("删除备份{}失败: {remove_err}", backup.name);
+                    }
+                    true
+                }
+                Err(restore_err) => {
+                    log::error!("回滚失败: {restore_err}，可以使用 restore {} 手动还原", backup.name);
+                    false
+                }
+            };
+            return Err(StepError {
+                step: name,
+                message: err.to_string(),
+                rolled_back,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn failed_step_rolls_back_in_reverse_order() {
+        let root = std::env::temp_dir().join(format!("llob_install_test_{}_transaction", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let backup_root = root.join("backups");
+        let existing = root.join("existing.txt");
+        fs::write(&existing, "original").unwrap();
+        let created_dir = root.join("created");
+        let created_file = created_dir.join("file.txt");
+
+        let mut backup = Backup::create(&backup_root, &root, &root).unwrap();
+        let steps = vec![
+            Step::new("first", vec![(existing.clone(), &[]), (created_dir.clone(), &[])], || {
+                fs::write(&existing, "changed")?;
+                fs::create_dir_all(&created_dir)?;
+                fs::write(&created_file, "first")?;
+                Ok(())
+            }),
+            // 修改第一步新建的文件后失败，必须先还原这个文件再删除目录，否则目录会被重新建出来
+            Step::new("second", vec![(created_file.clone(), &[])], || {
+                fs::write(&created_file, "second")?;
+                Err("second failed".into())
+            }),
+            Step::new("third", vec![], || panic!("failed steps must stop the transaction")),
+        ];
+        let err = run(&mut backup, steps).unwrap_err();
+
+        assert_eq!(err.step, "second");
+        assert_eq!(err.message, "second failed");
+        assert!(err.rolled_back);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "original");
+        assert!(!created_dir.exists());
+        assert!(Backup::list(&backup_root).unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}